@@ -0,0 +1,169 @@
+use std::ops::RangeInclusive;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    backend::lines,
+    data::{payload::Block, textrow::TextRow},
+};
+
+// Functions for working with the text within a block: which rows and graphemes it covers, and what its rows become when
+// its text is deleted, or when text is inserted as a block. Edits come back as (row, new text) pairs, in row order.
+
+// Returns the rows that a block covers, leaving out the empty line past the end of the document.
+// None if the block doesn't reach any real rows, as in a document with no rows at all.
+pub fn rows(data: &[TextRow], block: Block) -> Option<RangeInclusive<usize>> {
+    if block.top >= data.len() {
+        return None;
+    }
+    lines::clamp_rows(data.len(), block.top, block.bottom)
+}
+
+// Returns the grapheme range that a block covers on a row, clamped to the end of the row.
+pub fn span(row: &TextRow, block: Block, tab_width: usize) -> (usize, usize) {
+    let len = row.raw_text.graphemes(true).count();
+    (
+        row.grapheme_at_col(block.left, tab_width).min(len),
+        row.grapheme_at_col(block.right, tab_width).min(len),
+    )
+}
+
+// Returns the text within a block, with rows joined by newlines. Rows that end before the block starts give an empty line.
+pub fn text(data: &[TextRow], block: Block, tab_width: usize) -> String {
+    let rows = match rows(data, block) {
+        Some(rows) => rows,
+        None => return String::new(),
+    };
+    let mut text: Vec<String> = Vec::new();
+    for y in rows {
+        let (start, end) = span(&data[y], block, tab_width);
+        let g = data[y].raw_text.graphemes(true).collect::<Vec<&str>>();
+        text.push(g[start..end].concat());
+    }
+    text.join("\n")
+}
+
+// Returns the rows that change when the text within a block is deleted from every row it covers.
+pub fn delete(data: &[TextRow], block: Block, tab_width: usize) -> Vec<(usize, String)> {
+    let rows = match rows(data, block) {
+        Some(rows) => rows,
+        None => return Vec::new(),
+    };
+    let mut edits = Vec::new();
+    for y in rows {
+        let (start, end) = span(&data[y], block, tab_width);
+        if start < end {
+            let g = data[y].raw_text.graphemes(true).collect::<Vec<&str>>();
+            edits.push((y, g[..start].concat() + &g[end..].concat()));
+        }
+    }
+    edits
+}
+
+// Returns the grapheme index that a display column lands on in a row. If the row is too short to reach it, the row's text
+// padded out with spaces is returned as well.
+pub fn pad_to_col(row: &TextRow, col: usize, tab_width: usize) -> (usize, Option<String>) {
+    let len = row.raw_text.graphemes(true).count();
+    let x = row.grapheme_at_col(col, tab_width);
+    if x > len {
+        (x, Some(row.raw_text.clone() + &" ".repeat(x - len)))
+    } else {
+        (x, None)
+    }
+}
+
+// Inserts text as a block at a position: each line goes on the next row down, starting at the same display column.
+// Rows that are too short are padded out with spaces (unless their line is empty), and rows past the end of the document
+// are added. Returns the rows that change, and the position just after the text inserted on the first row.
+pub fn insert(
+    data: &[TextRow],
+    pos: (usize, usize),
+    text: &str,
+    tab_width: usize,
+) -> (Vec<(usize, String)>, (usize, usize)) {
+    let (x, y) = pos;
+    let col = match data.get(y) {
+        Some(row) => row.display_col(x, tab_width),
+        None => x,
+    };
+    let empty = TextRow::default();
+    let mut edits = Vec::new();
+    let mut after = pos;
+    for (i, line) in text.replace("\r\n", "\n").split('\n').enumerate() {
+        let row = data.get(y + i).unwrap_or(&empty);
+        if line.is_empty() {
+            // nothing goes on this row, so it's left as it is (but it's still added, if it's past the end)
+            if y + i >= data.len() {
+                edits.push((y + i, String::new()));
+            }
+            continue;
+        }
+        let (at, padded) = pad_to_col(row, col, tab_width);
+        let current = padded.unwrap_or_else(|| row.raw_text.clone());
+        let g = current.graphemes(true).collect::<Vec<&str>>();
+        edits.push((y + i, g[..at].concat() + line + &g[at..].concat()));
+        if i == 0 {
+            after = (at + line.graphemes(true).count(), y);
+        }
+    }
+    (edits, after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(rows: &[&str]) -> Vec<TextRow> {
+        rows.iter()
+            .map(|row| TextRow::new(row.to_string()))
+            .collect()
+    }
+
+    fn block(top: usize, bottom: usize, left: usize, right: usize) -> Block {
+        Block {
+            top,
+            bottom,
+            left,
+            right,
+        }
+    }
+
+    #[test]
+    fn empty_document() {
+        let data = document(&[]);
+        let b = block(0, 0, 0, 2);
+        assert_eq!(rows(&data, b), None);
+        assert_eq!(text(&data, b, 4), "");
+        assert!(delete(&data, b, 4).is_empty());
+        let (edits, after) = insert(&data, (0, 0), "ab\ncd", 4);
+        assert_eq!(edits, vec![(0, "ab".to_string()), (1, "cd".to_string())]);
+        assert_eq!(after, (2, 0));
+    }
+
+    #[test]
+    fn copy_and_delete() {
+        let data = document(&["abcd", "e", "\tfgh"]);
+        let b = block(0, 3, 1, 3);
+        assert_eq!(rows(&data, b), Some(0..=2));
+        assert_eq!(text(&data, b, 2), "bc\n\nf");
+        assert_eq!(
+            delete(&data, b, 2),
+            vec![(0, "ad".to_string()), (2, "\tgh".to_string())]
+        );
+    }
+
+    #[test]
+    fn insert_pads_short_rows() {
+        let data = document(&["abcd", "", "e"]);
+        let (edits, after) = insert(&data, (2, 0), "x\n\ny\nz", 4);
+        assert_eq!(
+            edits,
+            vec![
+                (0, "abxcd".to_string()),
+                (2, "e y".to_string()),
+                (3, "  z".to_string()),
+            ]
+        );
+        assert_eq!(after, (3, 0));
+    }
+}
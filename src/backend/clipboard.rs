@@ -0,0 +1,199 @@
+use crate::utils;
+use std::{
+    collections::VecDeque,
+    env,
+    io::{Error, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+// Clip Entry. A single piece of cut or copied text.
+// Line-wise entries were taken as whole lines (since nothing was selected, or the selection ran from the start of a line to
+// the start of a later one), and are pasted above the current line instead of at the cursor. (Or below it, with vim's p.)
+// Block entries were taken from a block selection, and are pasted as a block: each line goes on the next row down, at the same column.
+#[derive(Clone)]
+pub struct ClipEntry {
+    pub text: String,
+    pub linewise: bool,
+    pub block: bool,
+}
+
+impl ClipEntry {
+    pub fn new(text: String, linewise: bool) -> Self {
+        Self {
+            text,
+            linewise,
+            block: false,
+        }
+    }
+
+    // Creates an entry for text taken from a block selection.
+    pub fn block(text: String) -> Self {
+        Self {
+            text,
+            linewise: false,
+            block: true,
+        }
+    }
+}
+
+// KillRing. Holds the most recently cut or copied text, newest first. Only the last KILL_RING_SIZE entries are kept.
+// idx points at the entry that the next paste will use. Cycling moves it back through older entries, wrapping around at the end.
+pub struct KillRing {
+    entries: VecDeque<ClipEntry>,
+    idx: usize,
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            idx: 0,
+        }
+    }
+
+    // Adds a new entry to the front of the ring. The next paste will use it.
+    pub fn push(&mut self, entry: ClipEntry) {
+        self.entries.push_front(entry);
+        self.entries.truncate(KILL_RING_SIZE);
+        self.idx = 0;
+    }
+
+    // Adds text onto the end of the newest entry, so that a run of kills can be pasted back all at once.
+    // With nothing in the ring yet, this adds a new entry instead.
+    pub fn append(&mut self, text: &str) {
+        match self.entries.front_mut() {
+            Some(entry) => entry.text.push_str(text),
+            None => self
+                .entries
+                .push_front(ClipEntry::new(text.to_string(), false)),
+        }
+        self.idx = 0;
+    }
+
+    // Returns the newest entry, wherever cycling has got to.
+    pub fn newest(&self) -> Option<&ClipEntry> {
+        self.entries.front()
+    }
+
+    // Returns the entry that the next paste will use, if there is one.
+    pub fn current(&self) -> Option<&ClipEntry> {
+        self.entries.get(self.idx)
+    }
+
+    // Moves on to the next oldest entry, and returns it.
+    pub fn cycle(&mut self) -> Option<&ClipEntry> {
+        if !self.entries.is_empty() {
+            self.idx = (self.idx + 1) % self.entries.len();
+        }
+        self.current()
+    }
+}
+
+// SystemClipboard. Passes text to and from the host clipboard through a helper program (wl-copy, xclip, pbcopy...).
+// The helpers can be set with the GRAM_COPY_COMMAND and GRAM_PASTE_COMMAND environment variables. Otherwise, we look for
+// a known helper that matches the current display server. Either command might be missing, in which case it's skipped.
+pub struct SystemClipboard {
+    copy_cmd: Option<Vec<String>>,
+    paste_cmd: Option<Vec<String>>,
+}
+
+impl SystemClipboard {
+    pub fn new() -> Self {
+        let (copy, paste) = detect_helpers();
+        Self {
+            copy_cmd: env::var("GRAM_COPY_COMMAND")
+                .ok()
+                .or(copy)
+                .and_then(split_command),
+            paste_cmd: env::var("GRAM_PASTE_COMMAND")
+                .ok()
+                .or(paste)
+                .and_then(split_command),
+        }
+    }
+
+    // Hands text to the copy helper, by writing it to the helper's stdin.
+    // Helpers like xclip fork off to serve the clipboard. Their output is discarded, so the fork doesn't keep us waiting on a pipe.
+    // Some helpers only exit once the clipboard is taken by someone else, so they're written to and waited on from another
+    // thread. Only a helper that can't be started at all counts as an error.
+    pub fn copy(&self, text: &str) -> Result<(), Error> {
+        let cmd = match &self.copy_cmd {
+            Some(cmd) => cmd,
+            None => return Ok(()),
+        };
+        let mut child = Command::new(&cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        let text = text.to_string();
+        thread::spawn(move || {
+            if let Some(mut stdin) = stdin {
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            let _ = child.wait();
+        });
+        Ok(())
+    }
+
+    // Reads the host clipboard through the paste helper. Returns None if there's no helper, or it didn't succeed.
+    pub fn paste(&self) -> Option<String> {
+        let cmd = self.paste_cmd.as_ref()?;
+        let output = Command::new(&cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8(output.stdout).ok()
+    }
+}
+
+// Picks a copy and paste helper based on the display server we're running under, if one is installed.
+fn detect_helpers() -> (Option<String>, Option<String>) {
+    let helpers: &[(&str, bool, &str, &str)] = &[
+        (
+            "wl-copy",
+            env::var_os("WAYLAND_DISPLAY").is_some(),
+            "wl-copy",
+            "wl-paste --no-newline",
+        ),
+        (
+            "xclip",
+            env::var_os("DISPLAY").is_some(),
+            "xclip -selection clipboard",
+            "xclip -selection clipboard -o",
+        ),
+        (
+            "xsel",
+            env::var_os("DISPLAY").is_some(),
+            "xsel --clipboard --input",
+            "xsel --clipboard --output",
+        ),
+        ("pbcopy", cfg!(target_os = "macos"), "pbcopy", "pbpaste"),
+    ];
+    for (program, usable, copy, paste) in helpers {
+        if *usable && utils::program_exists(program) {
+            return (Some(copy.to_string()), Some(paste.to_string()));
+        }
+    }
+    (None, None)
+}
+
+// Splits a helper command into its program and arguments. An empty command counts as no command at all.
+fn split_command(cmd: String) -> Option<Vec<String>> {
+    let parts: Vec<String> = cmd.split_whitespace().map(String::from).collect();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts)
+    }
+}
+
+const KILL_RING_SIZE: usize = 16;
@@ -0,0 +1,230 @@
+use crate::{backend::lines::SortOptions, data::enums::InputEvent, keymap};
+use regex::Regex;
+
+// Functions for parsing the command line.
+
+// Command. A command typed into the command line, along with its arguments.
+// Action - Any command that can be bound to a key (like "copy" or "find-next"). Contains the InputEvent it runs.
+// Write - Save the file. Contains a new file name to save it under, if one was given.
+// WriteQuit - Save the file, then quit.
+// Edit - Open a different file. Contains its name, and whether to throw away unsaved changes to the current file.
+// Set - Change a setting for the open file. Contains the setting's name and its new value.
+// GoTo - Jump to a line. Contains the target, in any form that the go-to-line prompt understands.
+// Replace - Replace every occurrence of some text in the file. Contains the text to find, and what to replace it with.
+// Sort - Sort the selected lines (or every line). Contains how to compare them.
+// Unique - Remove repeated lines from the selected lines (or every line).
+// Reverse - Reverse the order of the selected lines (or every line).
+// Filter - Run the selected lines (or every line) through a shell command, and replace them with its output. Contains the command.
+pub enum Command {
+    Action(InputEvent),
+    Write(Option<String>),
+    WriteQuit,
+    Edit(String, bool),
+    Set(String, String),
+    GoTo(String),
+    Replace(String, String),
+    Sort(SortOptions),
+    Unique,
+    Reverse,
+    Filter(String),
+}
+
+// Returns the name of every command, for completion. Short aliases (like "w") are left out.
+pub fn command_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = LINE_COMMANDS.to_vec();
+    names.extend(keymap::command_names());
+    names
+}
+
+// Parses a line typed into the command line. A leading ':' is optional, so both ":w" and "w" save the file.
+// Anything after a '!' is a shell command to filter through, like vim's "!sort".
+// Returns an error message if the command doesn't exist, or its arguments don't make sense.
+pub fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let line = line.strip_prefix(':').unwrap_or(line).trim_start();
+    if let Some(cmd) = line.strip_prefix('!') {
+        return match cmd.trim() {
+            "" => Err("! needs a shell command.".to_string()),
+            cmd => Ok(Command::Filter(cmd.to_string())),
+        };
+    }
+    let (name, args) = match line.split_once(' ') {
+        Some((name, args)) => (name, args.trim()),
+        None => (line, ""),
+    };
+    let command = match (name, args) {
+        ("", _) => return Err("No command given.".to_string()),
+        ("w" | "write", "") => Command::Write(None),
+        ("w" | "write", path) => Command::Write(Some(path.to_string())),
+        ("wq" | "write-quit", "") => Command::WriteQuit,
+        ("q", "") => Command::Action(InputEvent::Quit),
+        ("e" | "edit" | "e!" | "edit!", "") => return Err(format!("{} needs a file name.", name)),
+        ("e" | "edit", path) => Command::Edit(path.to_string(), false),
+        ("e!" | "edit!", path) => Command::Edit(path.to_string(), true),
+        ("set", setting) => match setting.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() && !value.trim().is_empty() => {
+                Command::Set(key.trim().to_string(), value.trim().to_string())
+            }
+            _ => return Err("Use set like this: set tabwidth=4".to_string()),
+        },
+        ("goto", "") => return Err("goto needs a line number.".to_string()),
+        ("goto", target) => Command::GoTo(target.to_string()),
+        ("replace", args) => {
+            let (find, replace) = parse_replace(args)?;
+            Command::Replace(find, replace)
+        }
+        ("sort", args) => Command::Sort(parse_sort(args)?),
+        ("unique" | "uniq", "") => Command::Unique,
+        ("reverse", "") => Command::Reverse,
+        ("filter", cmd) if !cmd.is_empty() => Command::Filter(cmd.to_string()),
+        (name, "") => match keymap::command_event(name) {
+            Some(evt) => Command::Action(evt),
+            None => return Err(format!("Unknown command: {}", name)),
+        },
+        (name, _) if keymap::command_event(name).is_some() => {
+            return Err(format!("{} doesn't take any arguments.", name))
+        }
+        (name, _) => return Err(format!("Unknown command: {}", name)),
+    };
+    Ok(command)
+}
+
+// Parses the arguments to replace. Either two words ("replace old new"), or two pieces of text separated by a
+// punctuation character of your choosing, for text with spaces in it ("replace /old text/new text/").
+fn parse_replace(args: &str) -> Result<(String, String), String> {
+    let usage = "Use replace like this: replace old new, or replace /old/new/";
+    let delim = args.chars().next().ok_or(usage)?;
+    let parts: Vec<&str> = if delim.is_ascii_punctuation() {
+        let inner = &args[1..];
+        inner
+            .strip_suffix(delim)
+            .unwrap_or(inner)
+            .splitn(2, delim)
+            .collect()
+    } else {
+        args.split_whitespace().collect()
+    };
+    match parts[..] {
+        [find, replace] if !find.is_empty() => Ok((find.to_string(), replace.to_string())),
+        _ => Err(usage.to_string()),
+    }
+}
+
+// Parses the arguments to sort: any of the flags n (numeric), i (ignore case) and r (reverse), then an optional
+// /pattern/ that picks out the part of each line to sort by ("sort", "sort rn", "sort i /id=(\d+)/").
+fn parse_sort(args: &str) -> Result<SortOptions, String> {
+    let usage = "Use sort like this: sort [n][i][r] [/pattern/]";
+    let mut options = SortOptions::new();
+    let (flags, pattern) = match args.split_once('/') {
+        Some((flags, pattern)) => (flags, Some(pattern.strip_suffix('/').unwrap_or(pattern))),
+        None => (args, None),
+    };
+    for c in flags.chars().filter(|c| !c.is_whitespace()) {
+        match c {
+            'n' => options.numeric = true,
+            'i' => options.ignore_case = true,
+            'r' => options.reverse = true,
+            _ => return Err(usage.to_string()),
+        }
+    }
+    if let Some(pattern) = pattern {
+        if pattern.is_empty() {
+            return Err(usage.to_string());
+        }
+        let re = Regex::new(pattern).map_err(|_| format!("Not a valid pattern: {}", pattern))?;
+        options.key = Some(re);
+    }
+    Ok(options)
+}
+
+// CONSTS //
+
+// Commands that only exist on the command line, and can't be bound to a key.
+const LINE_COMMANDS: &[&str] = &[
+    "write",
+    "write-quit",
+    "edit",
+    "set",
+    "goto",
+    "replace",
+    "sort",
+    "unique",
+    "reverse",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_write() {
+        assert!(matches!(parse(":w"), Ok(Command::Write(None))));
+        assert!(matches!(parse("  write  "), Ok(Command::Write(None))));
+        assert!(matches!(parse(":w out.txt"), Ok(Command::Write(Some(path))) if path == "out.txt"));
+        assert!(matches!(parse("wq"), Ok(Command::WriteQuit)));
+        assert!(parse("wq now").is_err());
+    }
+
+    #[test]
+    fn parse_edit_and_set() {
+        assert!(matches!(parse("e a.rs"), Ok(Command::Edit(path, false)) if path == "a.rs"));
+        assert!(matches!(parse("e! a.rs"), Ok(Command::Edit(path, true)) if path == "a.rs"));
+        assert!(parse("edit").is_err());
+        assert!(matches!(
+            parse("set tabwidth = 2"),
+            Ok(Command::Set(key, value)) if key == "tabwidth" && value == "2"
+        ));
+        assert!(parse("set tabwidth").is_err());
+        assert!(parse("set =2").is_err());
+    }
+
+    #[test]
+    fn parse_replace_forms() {
+        assert!(matches!(
+            parse("replace foo bar"),
+            Ok(Command::Replace(find, replace)) if find == "foo" && replace == "bar"
+        ));
+        assert!(matches!(
+            parse("replace /a b/c d/"),
+            Ok(Command::Replace(find, replace)) if find == "a b" && replace == "c d"
+        ));
+        assert!(matches!(
+            parse("replace |x||"),
+            Ok(Command::Replace(find, replace)) if find == "x" && replace.is_empty()
+        ));
+        assert!(parse("replace foo").is_err());
+        assert!(parse("replace |x|").is_err());
+        assert!(parse("replace //x/").is_err());
+    }
+
+    #[test]
+    fn parse_sort_flags() {
+        match parse("sort nr /id=(\\d+)/") {
+            Ok(Command::Sort(options)) => {
+                assert!(options.numeric && options.reverse && !options.ignore_case);
+                assert_eq!(options.key.unwrap().as_str(), "id=(\\d+)");
+            }
+            _ => panic!("sort didn't parse"),
+        }
+        assert!(matches!(parse("sort"), Ok(Command::Sort(options)) if options.key.is_none()));
+        assert!(parse("sort x").is_err());
+        assert!(parse("sort //").is_err());
+        assert!(parse("sort /(/").is_err());
+    }
+
+    #[test]
+    fn parse_other_commands() {
+        assert!(matches!(parse("!sort -u"), Ok(Command::Filter(cmd)) if cmd == "sort -u"));
+        assert!(parse("!").is_err());
+        assert!(matches!(parse("uniq"), Ok(Command::Unique)));
+        assert!(matches!(parse("goto 12"), Ok(Command::GoTo(target)) if target == "12"));
+        assert!(matches!(
+            parse("copy"),
+            Ok(Command::Action(InputEvent::Copy))
+        ));
+        assert!(matches!(parse("q"), Ok(Command::Action(InputEvent::Quit))));
+        assert!(parse("copy now").is_err());
+        assert!(parse("nonsense").is_err());
+        assert!(parse(":").is_err());
+    }
+}
@@ -4,7 +4,12 @@ use crate::data::{payload::CursorState, textrow::TextRow};
 // cx and cy represent the x,y coords of the cursor's location.
 // row_offset and col_offset represent the degree to which the cursor is moved 'off-screen' on either axis.
 // Also stores the size of the terminal window (upon program initialization -- doesn't mutate) and its current state.
+// gutter is how many columns on the left of the window are taken up by line numbers. cols only counts the columns left for text.
+// tab_width is how many columns apart tab stops are, for working out how far across the screen the cursor is.
 // saved_state is used for saving and restoring the cursor's state (for prompt cancellation).
+// anchor is the document position where the current selection started. shift_select is set when that selection was started
+// with a Shift+movement key, in which case any plain movement drops the selection (a mark set explicitly sticks around).
+// block is set when the selection is a block selection.
 pub struct CursorHandler {
     pub cx: i16,
     pub cy: i16,
@@ -12,8 +17,13 @@ pub struct CursorHandler {
     col_offset: i16,
     rows: u16,
     cols: u16,
+    gutter: u16,
+    tab_width: usize,
     state: CursorState,
     saved_state: CursorState,
+    anchor: Option<(usize, usize)>,
+    shift_select: bool,
+    block: bool,
 }
 
 impl CursorHandler {
@@ -26,8 +36,13 @@ impl CursorHandler {
             col_offset: 0,
             rows,
             cols,
+            gutter: 0,
+            tab_width: 1,
             state: CursorState::new(),
             saved_state: CursorState::new(),
+            anchor: None,
+            shift_select: false,
+            block: false,
         }
     }
 
@@ -41,6 +56,80 @@ impl CursorHandler {
         self.state = self
             .state
             .update(self.cx, self.cy, self.row_offset, self.col_offset);
+        self.state.anchor = self.anchor;
+        self.state.block = self.block;
+    }
+
+    // Drops an anchor at the cursor's current position, starting a selection. Does nothing if a selection is already in progress.
+    // shift: whether this selection is being started by a Shift+movement key.
+    pub fn set_anchor(&mut self, shift: bool) {
+        if self.anchor.is_none() {
+            self.anchor = Some(self.state.position());
+            self.shift_select = shift;
+            self.update_state();
+        }
+    }
+
+    // Drops an anchor at the cursor's current position, starting a block selection. Like a mark, it stays set as the cursor moves.
+    pub fn set_block_anchor(&mut self) {
+        self.anchor = Some(self.state.position());
+        self.shift_select = false;
+        self.block = true;
+        self.update_state();
+    }
+
+    // Moves the anchor of the current selection to a new position, if there is a selection.
+    // Used when an edit shifts the text underneath the anchor.
+    pub fn move_anchor(&mut self, pos: (usize, usize)) {
+        if self.anchor.is_some() {
+            self.anchor = Some(pos);
+            self.update_state();
+        }
+    }
+
+    // Clears the current selection, if there is one.
+    pub fn clear_anchor(&mut self) {
+        self.anchor = None;
+        self.shift_select = false;
+        self.block = false;
+        self.update_state();
+    }
+
+    // Clears the current selection, but only if it was started by a Shift+movement key.
+    pub fn release_shift_anchor(&mut self) {
+        if self.shift_select {
+            self.clear_anchor();
+        }
+    }
+
+    // Whether or not there's a selection in progress.
+    pub fn has_anchor(&self) -> bool {
+        self.anchor.is_some()
+    }
+
+    // Sets how many columns the line number gutter takes up. If that changes, the cursor is put back in the same spot
+    // in the document, so that the view scrolls to keep it on screen. Returns whether the width changed.
+    pub fn set_gutter(&mut self, gutter: u16, data: &[TextRow]) -> bool {
+        if gutter == self.gutter {
+            return false;
+        }
+        let (x, y) = self.state.position();
+        self.cols = self.cols + self.gutter - gutter;
+        self.gutter = gutter;
+        self.teleport(x, y, data);
+        true
+    }
+
+    // Sets how many columns apart tab stops are. If that changes, the cursor is put back in the same spot in the document,
+    // so that the view scrolls to keep it on screen. Returns whether the width changed.
+    pub fn set_tab_width(&mut self, tab_width: usize, data: &[TextRow]) -> bool {
+        if tab_width == self.tab_width {
+            return false;
+        }
+        let (x, y) = self.state.position();
+        self.tab_width = tab_width;
+        self.teleport(x, y, data);
+        true
     }
 
     // Backup the current cursor state. Used on prompt initialization.
@@ -48,6 +137,11 @@ impl CursorHandler {
         self.saved_state = self.state;
     }
 
+    // Returns the saved cursor state. Used by incremental prompts that need to know where they started from.
+    pub fn get_saved_state(&self) -> CursorState {
+        self.saved_state
+    }
+
     // Restore the current cursor state from the saved state. Used on prompt cancellation.
     pub fn restore_state(&mut self) {
         self.cx = self.saved_state.cx;
@@ -67,6 +161,69 @@ impl CursorHandler {
         } else {
             self.handle_y_move(val, data);
         }
+        self.keep_in_view(data);
+    }
+
+    // Move the cursor straight to a position in the document, given as a grapheme index and a row index.
+    // If the target is already on screen, only cx and cy change. Otherwise, the row offset is adjusted so that the target
+    // row lands in the middle of the screen, and the column offset is adjusted like the "teleport case" in handle_x_move.
+    // Updates its CursorState after all values have been changed.
+    pub fn teleport(&mut self, x: usize, y: usize, data: &[TextRow]) {
+        let height = (self.rows - 2) as i16;
+        let y = y.min(data.len()) as i16;
+        if y < self.row_offset || y >= self.row_offset + height {
+            self.row_offset = (y - height / 2).max(0);
+        }
+        self.cy = y - self.row_offset;
+
+        let line_len = data.get(y as usize).map_or(0, |row| row.length() - 1);
+        let x = (x as i16).min(line_len);
+        let width = (self.cols - 1) as i16;
+        if x < self.col_offset || x >= self.col_offset + width {
+            self.col_offset = (x - (self.cols - 5) as i16).max(0);
+        }
+        self.cx = x - self.col_offset;
+
+        self.keep_in_view(data);
+    }
+
+    // Converts a position on screen (a 0-indexed column and row) into a position in the document, using the current offsets.
+    // Returns None if the position is outside of the text area (e.g. on the status bar).
+    // The row is clamped to the empty line past the end of the document, and the column to the end of its line.
+    // Tabs and wide characters take up more than one column, so the column is converted with the row's display widths.
+    pub fn screen_to_document(&self, x: u16, y: u16, data: &[TextRow]) -> Option<(usize, usize)> {
+        if y >= self.rows - 2 {
+            return None;
+        }
+        let row = (y as usize + self.row_offset as usize).min(data.len());
+        let x = x.saturating_sub(self.gutter) as usize;
+        let col = match data.get(row) {
+            Some(r) => {
+                // the screen column is measured from the first visible column, which may not be the row's first
+                let at = r.display_col(self.col_offset as usize, self.tab_width) + x;
+                let mut g = r.grapheme_at_col(at, self.tab_width);
+                // a click on the right half of a wide character (or anywhere in a tab) lands on that grapheme
+                if g > 0 && r.display_col(g, self.tab_width) > at {
+                    g -= 1;
+                }
+                g.min(r.length() as usize - 1)
+            }
+            None => 0,
+        };
+        Some((col, row))
+    }
+
+    // Scrolls the view by a number of rows (negative scrolls up), without moving the cursor within the document.
+    // The view can scroll until the empty line past the end of the document is at the top. If the cursor would end up
+    // off-screen, it's pulled back onto the nearest visible row.
+    // Updates its CursorState after all values have been changed.
+    pub fn scroll_view(&mut self, delta: i16, data: &[TextRow]) {
+        let height = (self.rows - 2) as i16;
+        let row = self.cy + self.row_offset;
+        self.row_offset = (self.row_offset + delta).clamp(0, data.len() as i16);
+        self.cy = (row - self.row_offset).clamp(0, height - 1);
+        self.check_and_fix_cx(data);
+        self.keep_in_view(data);
     }
 
     // More stripped-down version of handle_cursor, used to handle scroll events.
@@ -86,7 +243,7 @@ impl CursorHandler {
             (false, false) => self.wrap_cy_to_end(data),
         }
 
-        self.update_state();
+        self.keep_in_view(data);
     }
 
     // Handle a cursor move along the y-axis, with a proposed cy value and a reference to the RenderDriver's current data.
@@ -109,7 +266,7 @@ impl CursorHandler {
         }
         if val != -1
             && val != (self.rows - 1).try_into().unwrap()
-            && val <= data.len().try_into().unwrap()
+            && val + self.row_offset <= data.len().try_into().unwrap()
         {
             // moving within the document
             self.cy = val;
@@ -136,32 +293,25 @@ impl CursorHandler {
                 has_wrapped = true;
             }
         }
-        if val == (self.cols - 1).try_into().unwrap() {
-            // offscreen to the right
-            if data[self.cy as usize].length() >= val + self.col_offset {
-                // is there more data to show here?
-                self.col_offset += 1;
-            } else if data.len() + 1 > self.cy.try_into().unwrap() {
-                // is there a line we can wrap to?
-                self.cy += 1;
-                self.cx = 0;
-                self.col_offset = 0;
-                has_wrapped = true;
-            }
-        }
-        if val == data[self.cy as usize].length() + 1
-            || (val + self.col_offset >= data[self.cy as usize].length() + 1)
+        if val == (self.cols - 1).try_into().unwrap()
+            && self.row_length(data) > val + self.col_offset
         {
+            // offscreen to the right, with more data to show here (going past the end of the line is handled below)
+            self.col_offset += 1;
+            self.cx = val - 1;
+            has_wrapped = true;
+        }
+        if !has_wrapped && val + self.col_offset >= self.row_length(data) {
             // end of line
-            if data.len() + 1 > self.cy.try_into().unwrap() {
+            if self.row() + 1 < data.len() {
                 // is there a line we can wrap to?
                 self.cy += 1;
                 self.cx = 0;
                 self.col_offset = 0;
-                has_wrapped = true;
             }
+            has_wrapped = true;
         }
-        if val > self.cols.try_into().unwrap() && val <= data[self.cy as usize].length() + 1 {
+        if val > self.cols.try_into().unwrap() && val <= self.row_length(data) + 1 {
             // "teleport case" -- impossible to receive this val otherwise
             self.col_offset = val - (self.cols - 5) as i16;
             self.cx = val - self.col_offset;
@@ -175,15 +325,47 @@ impl CursorHandler {
         self.update_state();
     }
 
+    // cx and col_offset count graphemes, but tabs and wide characters take up more than one column on screen. If they push
+    // the cursor past the right edge of the window, the view is scrolled along until it fits.
+    // Updates its CursorState after all values have been changed.
+    fn keep_in_view(&mut self, data: &[TextRow]) {
+        if let Some(row) = data.get(self.row()) {
+            let x = (self.cx + self.col_offset) as usize;
+            let at = row.display_col(x, self.tab_width);
+            let width = (self.cols - 1) as usize;
+            if at - row.display_col(self.col_offset as usize, self.tab_width) >= width {
+                let offset = row.grapheme_at_col(at + 1 - width, self.tab_width).min(x);
+                self.col_offset = offset as i16;
+                self.cx = (x - offset) as i16;
+            }
+        }
+        self.update_state();
+    }
+
+    // Returns the length of the row the cursor is on, as counted by TextRow::length. The empty row past the end of the
+    // document doesn't exist yet, so it counts as empty.
+    fn row_length(&self, data: &[TextRow]) -> i16 {
+        data.get(self.row()).map_or(1, |row| row.length())
+    }
+
+    // Returns the index of the document row the cursor is currently on.
+    fn row(&self) -> usize {
+        (self.cy + self.row_offset) as usize
+    }
+
     // Helper method for wrapping cx to the end of the line indexed by the handler's current cy.
-    // Sets cx to the very end of this line, adding col_offset if the line is long enough.
+    // Sets cx to the very end of this line, adding col_offset if the line is too long to fit in the visible columns.
     // Doesn't update its CursorState -- this should be done by the calling function.
     fn wrap_cx_to_end(&mut self, data: &[TextRow]) {
-        let line_len = data[self.cy as usize].length();
-        if line_len > self.rows.try_into().unwrap() {
-            self.col_offset = line_len.wrapping_sub(self.rows.try_into().unwrap());
-            self.cx = line_len - self.col_offset;
+        // length() counts one past the last grapheme, and the empty row past the end of the document has none
+        let line_len = data.get(self.row()).map_or(0, |row| row.length() - 1);
+        // the last column is treated as off-screen, like in handle_x_move
+        let last_col = (self.cols - 2) as i16;
+        if line_len > last_col {
+            self.col_offset = line_len - last_col;
+            self.cx = last_col;
         } else {
+            self.col_offset = 0;
             self.cx = line_len;
         }
     }
@@ -206,13 +388,13 @@ impl CursorHandler {
     // Mostly useful for use after cy is forcibly changed by a wrap or scroll event.
     // Doesn't update its CursorState -- this should be done by the calling function.
     fn check_and_fix_cx(&mut self, data: &[TextRow]) {
-        if data.len() > self.cy.try_into().unwrap() && self.cx > data[self.cy as usize].length() {
-            self.cx = data[self.cy as usize].length();
+        if data.len() > self.row() && self.cx > data[self.row()].length() - 1 {
+            self.cx = data[self.row()].length() - 1;
             self.col_offset = 0;
         }
 
         // if final line in editor
-        if data.len() == self.cy.try_into().unwrap() {
+        if data.len() == self.row() {
             self.cx = 0;
             self.col_offset = 0;
         }
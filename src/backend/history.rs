@@ -0,0 +1,348 @@
+use std::collections::VecDeque;
+
+use crate::data::textrow::TextRow;
+
+// Functions for undoing and redoing changes to the text.
+
+// Edit. A change to the document, kept as just the rows it replaced, rather than a copy of the whole document.
+// start is the first row that changed. old holds the rows that were there before, and new holds the rows that took their place.
+// before and after are where the cursor was before and after the change. from and to are the versions of the document
+// before and after it.
+struct Edit {
+    start: usize,
+    old: Vec<String>,
+    new: Vec<String>,
+    before: (usize, usize),
+    after: (usize, usize),
+    from: usize,
+    to: usize,
+}
+
+impl Edit {
+    // How many bytes of text the edit holds onto.
+    fn size(&self) -> usize {
+        self.old.iter().chain(&self.new).map(|row| row.len()).sum()
+    }
+}
+
+// Restore. What undoing or redoing a change does to the document: starting at row start, remove rows are taken out,
+// and rows are put in their place. cursor is where the cursor was at the time.
+pub struct Restore {
+    pub start: usize,
+    pub remove: usize,
+    pub rows: Vec<String>,
+    pub cursor: (usize, usize),
+}
+
+// History. Holds the changes made to the document, so that they can be undone (and then redone).
+// base is a copy of the document as of the last recorded change. A change is worked out by comparing the document against
+// it, once the change is over: at the next checkpoint, or at an undo. open holds the cursor and version from the start
+// of the change in progress, if there is one.
+// merging holds the kind of change that the open change was started for, if the next change of the same kind can be
+// folded into it -- so that a run of typing (or of deleting) is undone all at once.
+// While grouped, no new changes are started, so everything done in the meantime is undone as a single change.
+// version is the version of the document as it is now, and versions counts how many have been handed out. saved is the
+// version that was last saved (None if that version can't be returned to).
+// undo_bytes is how much text the changes in undo hold onto. Past UNDO_BYTES, the oldest changes are forgotten.
+pub struct History {
+    base: Vec<String>,
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    undo_bytes: usize,
+    open: Option<((usize, usize), usize)>,
+    merging: Option<&'static str>,
+    grouped: bool,
+    version: usize,
+    versions: usize,
+    saved: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            base: Vec::new(),
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            undo_bytes: 0,
+            open: None,
+            merging: None,
+            grouped: false,
+            version: 0,
+            versions: 0,
+            saved: Some(0),
+        }
+    }
+
+    // Starts a new change, just before the document is changed, with the cursor at a given position. Passing a kind of
+    // change lets the next change of the same kind be folded into this one. The change before it is recorded first.
+    pub fn checkpoint(
+        &mut self,
+        merge: Option<&'static str>,
+        data: &[TextRow],
+        cursor: (usize, usize),
+    ) {
+        if self.grouped {
+            return;
+        }
+        let merged = merge.is_some() && merge == self.merging;
+        self.merging = merge;
+        if merged {
+            return;
+        }
+        self.close(data, cursor);
+        self.open = Some((cursor, self.version));
+        self.versions += 1;
+        self.version = self.versions;
+    }
+
+    // Stops the next change from being folded into the last one.
+    pub fn break_merge(&mut self) {
+        self.merging = None;
+    }
+
+    // Records the open change, by comparing the document against base. A change that didn't change anything isn't
+    // recorded, and the document goes back to the version it started out as.
+    fn close(&mut self, data: &[TextRow], cursor: (usize, usize)) {
+        let (before, from) = match self.open.take() {
+            Some(open) => open,
+            None => return,
+        };
+        let (start, end, new_end) = match diff(&self.base, data) {
+            Some(range) => range,
+            None => {
+                if self.saved == Some(self.version) {
+                    self.saved = Some(from);
+                }
+                self.version = from;
+                return;
+            }
+        };
+        let new: Vec<String> = data[start..new_end]
+            .iter()
+            .map(|row| row.raw_text.clone())
+            .collect();
+        let old: Vec<String> = self.base.splice(start..end, new.iter().cloned()).collect();
+        self.push_undo(Edit {
+            start,
+            old,
+            new,
+            before,
+            after: cursor,
+            from,
+            to: self.version,
+        });
+        self.redo.clear();
+    }
+
+    // Adds a change to the undo stack, forgetting the oldest ones if there's too much text held onto. The newest change
+    // is always kept, however big it is.
+    fn push_undo(&mut self, edit: Edit) {
+        self.undo_bytes += edit.size();
+        self.undo.push_back(edit);
+        while self.undo_bytes > UNDO_BYTES && self.undo.len() > 1 {
+            let oldest = self.undo.pop_front().expect("Undo history was empty.");
+            self.undo_bytes -= oldest.size();
+        }
+    }
+
+    // Steps back to before the last change, holding onto it so that it can be redone.
+    // Returns how to put the document back, or None if there's nothing to undo.
+    pub fn undo(&mut self, data: &[TextRow], cursor: (usize, usize)) -> Option<Restore> {
+        self.close(data, cursor);
+        self.merging = None;
+        let edit = self.undo.pop_back()?;
+        self.undo_bytes -= edit.size();
+        let restore = Restore {
+            start: edit.start,
+            remove: edit.new.len(),
+            rows: edit.old.clone(),
+            cursor: edit.before,
+        };
+        self.base.splice(
+            edit.start..edit.start + edit.new.len(),
+            edit.old.iter().cloned(),
+        );
+        self.version = edit.from;
+        self.redo.push(edit);
+        Some(restore)
+    }
+
+    // Steps forward again, making the last change that was undone, and holding onto it so that it can be undone again.
+    // Returns how to change the document, or None if there's nothing to redo.
+    pub fn redo(&mut self, data: &[TextRow], cursor: (usize, usize)) -> Option<Restore> {
+        self.close(data, cursor);
+        self.merging = None;
+        let edit = self.redo.pop()?;
+        let restore = Restore {
+            start: edit.start,
+            remove: edit.old.len(),
+            rows: edit.new.clone(),
+            cursor: edit.after,
+        };
+        self.base.splice(
+            edit.start..edit.start + edit.old.len(),
+            edit.new.iter().cloned(),
+        );
+        self.version = edit.to;
+        self.push_undo(edit);
+        Some(restore)
+    }
+
+    // Starts a change, then holds off on starting any more until the group ends.
+    pub fn begin_group(&mut self, data: &[TextRow], cursor: (usize, usize)) {
+        self.checkpoint(None, data, cursor);
+        self.grouped = true;
+    }
+
+    // Ends a group, so that the next change gets a checkpoint of its own.
+    pub fn end_group(&mut self) {
+        self.grouped = false;
+        self.merging = None;
+    }
+
+    // Remembers that the document was saved as it is now. The next change gets a checkpoint of its own, so that undoing it
+    // comes back to exactly this version. Inside a group, changes don't get their own versions, so there's no coming back.
+    pub fn mark_saved(&mut self) {
+        self.saved = match self.grouped {
+            true => None,
+            false => Some(self.version),
+        };
+        self.merging = None;
+    }
+
+    // Whether the document is the same version that was last saved.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.version)
+    }
+
+    // Forgets every change, and starts again from the document as it is. Used when a different file is opened, which
+    // counts as saved.
+    pub fn clear(&mut self, data: &[TextRow]) {
+        self.base = data.iter().map(|row| row.raw_text.clone()).collect();
+        self.undo.clear();
+        self.redo.clear();
+        self.undo_bytes = 0;
+        self.open = None;
+        self.merging = None;
+        self.saved = Some(self.version);
+    }
+}
+
+// Compares a document against an older copy of it. Returns the range of rows that changed: the first row that's
+// different, and where the rows that are the same at the end start, in the old copy and in the document.
+// None if they're the same.
+fn diff(old: &[String], data: &[TextRow]) -> Option<(usize, usize, usize)> {
+    let start = old
+        .iter()
+        .zip(data)
+        .take_while(|(a, b)| **a == b.raw_text)
+        .count();
+    if start == old.len() && start == data.len() {
+        return None;
+    }
+    let same = old.len().min(data.len()) - start;
+    let end = old
+        .iter()
+        .rev()
+        .zip(data.iter().rev())
+        .take(same)
+        .take_while(|(a, b)| **a == b.raw_text)
+        .count();
+    Some((start, old.len() - end, data.len() - end))
+}
+
+// CONSTS //
+
+// How many bytes of text the undo history can hold onto.
+const UNDO_BYTES: usize = 16 * 1024 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(rows: &[&str]) -> Vec<TextRow> {
+        rows.iter()
+            .map(|row| TextRow::new(row.to_string()))
+            .collect()
+    }
+
+    // Puts a restore into a document, the way the editor does.
+    fn apply(data: &mut Vec<TextRow>, restore: Restore) {
+        let rows = restore.rows.into_iter().map(TextRow::new);
+        data.splice(restore.start..restore.start + restore.remove, rows);
+    }
+
+    fn text(data: &[TextRow]) -> Vec<&str> {
+        data.iter().map(|row| row.raw_text.as_str()).collect()
+    }
+
+    #[test]
+    fn only_changed_rows_are_kept() {
+        let mut data = document(&["a", "b", "c", "d"]);
+        let mut history = History::new();
+        history.clear(&data);
+        history.checkpoint(None, &data, (0, 1));
+        data[1] = TextRow::new("B".to_string());
+        data.insert(2, TextRow::new("x".to_string()));
+        history.checkpoint(None, &data, (1, 2));
+        let edit = history.undo.back().unwrap();
+        assert_eq!((edit.start, edit.old.len(), edit.new.len()), (1, 1, 2));
+        assert_eq!(history.undo_bytes, 3);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut data = document(&["one", "two"]);
+        let mut history = History::new();
+        history.clear(&data);
+        history.checkpoint(None, &data, (0, 0));
+        data.remove(0);
+        history.checkpoint(None, &data, (0, 0));
+        data[0] = TextRow::new("three".to_string());
+
+        let restore = history.undo(&data, (5, 0)).unwrap();
+        apply(&mut data, restore);
+        assert_eq!(text(&data), vec!["two"]);
+        let restore = history.undo(&data, (0, 0)).unwrap();
+        assert_eq!(restore.cursor, (0, 0));
+        apply(&mut data, restore);
+        assert_eq!(text(&data), vec!["one", "two"]);
+        assert!(history.is_saved());
+        assert!(history.undo(&data, (0, 0)).is_none());
+
+        let restore = history.redo(&data, (0, 0)).unwrap();
+        apply(&mut data, restore);
+        let restore = history.redo(&data, (0, 0)).unwrap();
+        assert_eq!(restore.cursor, (5, 0));
+        apply(&mut data, restore);
+        assert_eq!(text(&data), vec!["three"]);
+        assert!(!history.is_saved());
+    }
+
+    #[test]
+    fn unchanged_checkpoints_are_dropped() {
+        let data = document(&["a"]);
+        let mut history = History::new();
+        history.clear(&data);
+        history.checkpoint(None, &data, (0, 0));
+        history.mark_saved();
+        history.checkpoint(None, &data, (0, 0));
+        assert!(history.undo(&data, (0, 0)).is_none());
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn oldest_changes_are_forgotten() {
+        let big = "x".repeat(UNDO_BYTES / 2);
+        let mut data = document(&[""]);
+        let mut history = History::new();
+        history.clear(&data);
+        for i in 0..3 {
+            history.checkpoint(None, &data, (0, 0));
+            data[0] = TextRow::new(format!("{}{}", big, i));
+        }
+        history.checkpoint(None, &data, (0, 0));
+        assert_eq!(history.undo.len(), 1);
+        assert!(history.undo_bytes <= UNDO_BYTES + big.len() * 2);
+    }
+}
@@ -0,0 +1,198 @@
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+// Functions for rearranging lines: sorting them, removing duplicates and reversing their order.
+
+// SortOptions. How the sort command compares lines.
+// numeric compares the first number in each line, rather than its text. Lines without a number come first.
+// ignore_case compares text without regard to case. reverse sorts from last to first.
+// key picks out the part of each line to compare: its first capture group if it has one, or else the whole match.
+// Lines that it doesn't match come first, in their original order.
+pub struct SortOptions {
+    pub numeric: bool,
+    pub ignore_case: bool,
+    pub reverse: bool,
+    pub key: Option<Regex>,
+}
+
+impl SortOptions {
+    pub fn new() -> Self {
+        Self {
+            numeric: false,
+            ignore_case: false,
+            reverse: false,
+            key: None,
+        }
+    }
+
+    // Picks out the part of a line that gets compared. None if the key pattern doesn't match it.
+    fn key_of<'a>(&self, line: &'a str) -> Option<&'a str> {
+        match &self.key {
+            None => Some(line),
+            Some(re) => re
+                .captures(line)
+                .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
+                .map(|m| m.as_str()),
+        }
+    }
+
+    // Compares two lines. The sort is stable, so lines that compare equal keep their order.
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let ord = match (self.key_of(a), self.key_of(b)) {
+            (Some(a), Some(b)) if self.numeric => {
+                let (a, b) = (first_number(a), first_number(b));
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            }
+            (Some(a), Some(b)) if self.ignore_case => a.to_lowercase().cmp(&b.to_lowercase()),
+            (a, b) => a.cmp(&b),
+        };
+        if self.reverse {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+// Sorts lines in place.
+pub fn sort(lines: &mut [String], options: &SortOptions) {
+    lines.sort_by(|a, b| options.compare(a, b));
+}
+
+// Removes every line that's a repeat of one before it, wherever it is.
+pub fn unique(lines: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    lines
+        .into_iter()
+        .filter(|line| seen.insert(line.clone()))
+        .collect()
+}
+
+// Clamps a range of rows to a document with a given number of rows. A range that starts past the end is moved back to
+// the last row. None if the document has no rows at all (an empty file, or no file).
+pub fn clamp_rows(len: usize, first: usize, last: usize) -> Option<RangeInclusive<usize>> {
+    let last = last.min(len.checked_sub(1)?);
+    Some(first.min(last)..=last)
+}
+
+// Counts how many lines differ between two versions of the same range, including any that were added or removed.
+pub fn count_changed(old: &[String], new: &[String]) -> usize {
+    let moved = old.iter().zip(new).filter(|(a, b)| a != b).count();
+    moved + old.len().abs_diff(new.len())
+}
+
+// Finds the first number in some text, which might be negative or have a decimal part.
+fn first_number(text: &str) -> Option<f64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let negative = text[..start].ends_with('-');
+    let digits: String = text[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    let digits = digits.trim_end_matches('.');
+    let value: f64 = digits.parse().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_rows_empty_document() {
+        assert_eq!(clamp_rows(0, 0, 0), None);
+        assert_eq!(clamp_rows(0, 2, 5), None);
+    }
+
+    #[test]
+    fn clamp_rows_past_the_end() {
+        assert_eq!(clamp_rows(3, 1, 2), Some(1..=2));
+        assert_eq!(clamp_rows(3, 0, 10), Some(0..=2));
+        assert_eq!(clamp_rows(3, 3, 3), Some(2..=2));
+    }
+
+    fn strings(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn sorted(lines: &[&str], options: SortOptions) -> Vec<String> {
+        let mut lines = strings(lines);
+        sort(&mut lines, &options);
+        lines
+    }
+
+    #[test]
+    fn sort_text() {
+        let lines = ["b", "C", "a", "c"];
+        assert_eq!(
+            sorted(&lines, SortOptions::new()),
+            strings(&["C", "a", "b", "c"])
+        );
+        let mut options = SortOptions::new();
+        options.reverse = true;
+        assert_eq!(sorted(&lines, options), strings(&["c", "b", "a", "C"]));
+    }
+
+    #[test]
+    fn sort_numeric() {
+        let mut options = SortOptions::new();
+        options.numeric = true;
+        assert_eq!(
+            sorted(&["x10", "x9", "none", "x-3", "x2.5"], options),
+            strings(&["none", "x-3", "x2.5", "x9", "x10"])
+        );
+    }
+
+    #[test]
+    fn sort_ignore_case() {
+        let mut options = SortOptions::new();
+        options.ignore_case = true;
+        assert_eq!(
+            sorted(&["b", "C", "a", "A"], options),
+            strings(&["a", "A", "b", "C"])
+        );
+    }
+
+    #[test]
+    fn sort_key() {
+        let mut options = SortOptions::new();
+        options.key = Some(Regex::new(r"id=(\w+)").unwrap());
+        assert_eq!(
+            sorted(&["z id=b", "no key", "y id=a", "also none"], options),
+            strings(&["no key", "also none", "y id=a", "z id=b"])
+        );
+    }
+
+    #[test]
+    fn sort_is_stable() {
+        let mut options = SortOptions::new();
+        options.numeric = true;
+        assert_eq!(
+            sorted(&["2 b", "1 z", "2 a", "1 y"], options),
+            strings(&["1 z", "1 y", "2 b", "2 a"])
+        );
+        let mut options = SortOptions::new();
+        options.ignore_case = true;
+        assert_eq!(sorted(&["B", "a", "b"], options), strings(&["a", "B", "b"]));
+    }
+
+    #[test]
+    fn unique_keeps_first() {
+        assert_eq!(
+            unique(strings(&["a", "b", "a", "", "b", "c", ""])),
+            strings(&["a", "b", "", "c"])
+        );
+    }
+
+    #[test]
+    fn first_number_forms() {
+        assert_eq!(first_number("abc"), None);
+        assert_eq!(first_number("v12 and 3"), Some(12.0));
+        assert_eq!(first_number("x -4.5"), Some(-4.5));
+        assert_eq!(first_number("a- 4"), Some(4.0));
+        assert_eq!(first_number("end 7."), Some(7.0));
+        assert_eq!(first_number("1.2.3"), None);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::data::enums::InputEvent;
+use std::collections::HashMap;
+
+// Functions for recording and replaying keyboard macros.
+
+// MacroRecorder. Records the events handled by the editor into named registers, so that they can be replayed later.
+// recording holds the register being recorded into, along with everything recorded so far.
+// last is the register that was last recorded or played. Leaving the register name blank uses it again.
+pub struct MacroRecorder {
+    registers: HashMap<String, Vec<InputEvent>>,
+    recording: Option<(String, Vec<InputEvent>)>,
+    last: Option<String>,
+}
+
+impl MacroRecorder {
+    pub fn new() -> Self {
+        Self {
+            registers: HashMap::new(),
+            recording: None,
+            last: None,
+        }
+    }
+
+    // Starts recording into a register. A blank name means the last register used (or DEFAULT_REGISTER, at first).
+    // Returns the register's name.
+    pub fn start(&mut self, name: &str) -> String {
+        let name = self.resolve(name);
+        self.recording = Some((name.clone(), Vec::new()));
+        name
+    }
+
+    // Stops recording, and saves everything recorded into its register. Returns the register's name and how many events
+    // were saved, or None if nothing was being recorded.
+    pub fn stop(&mut self) -> Option<(String, usize)> {
+        let (name, events) = self.recording.take()?;
+        let count = events.len();
+        self.registers.insert(name.clone(), events);
+        self.last = Some(name.clone());
+        Some((name, count))
+    }
+
+    // Records an event, if a macro is being recorded.
+    pub fn record(&mut self, evt: &InputEvent) {
+        if let Some((_, events)) = &mut self.recording {
+            events.push(evt.clone());
+        }
+    }
+
+    // Returns the events saved in a register, along with the register's name. A blank name means the last register used.
+    pub fn get(&mut self, name: &str) -> Result<(String, Vec<InputEvent>), String> {
+        let name = self.resolve(name);
+        match self.registers.get(&name) {
+            Some(events) => {
+                self.last = Some(name.clone());
+                Ok((name, events.clone()))
+            }
+            None => Err(name),
+        }
+    }
+
+    // Fills in a blank register name.
+    fn resolve(&self, name: &str) -> String {
+        match name.trim() {
+            "" => self
+                .last
+                .clone()
+                .unwrap_or_else(|| DEFAULT_REGISTER.to_string()),
+            name => name.to_string(),
+        }
+    }
+}
+
+// Parses what was typed into the play-macro prompt: a register name and a repeat count, either of which can be left out
+// ("a", "a 5", "5" or nothing at all). Returns None if the count isn't a number.
+pub fn parse_play(text: &str) -> Option<(String, usize)> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    match words[..] {
+        [] => Some((String::new(), 1)),
+        [word] => match word.parse::<usize>() {
+            Ok(count) => Some((String::new(), count)),
+            Err(_) => Some((word.to_string(), 1)),
+        },
+        [name, count] => count.parse().ok().map(|count| (name.to_string(), count)),
+        _ => None,
+    }
+}
+
+// CONSTS //
+
+// The register used when none is given, before any register has been used.
+const DEFAULT_REGISTER: &str = "q";
@@ -1,3 +1,11 @@
+pub mod blocks;
+pub mod clipboard;
+pub mod commands;
 pub mod cursor;
+pub mod history;
+pub mod lines;
+pub mod macros;
 pub mod operations;
 pub mod prompt;
+pub mod shell;
+pub mod syntax;
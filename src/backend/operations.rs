@@ -11,18 +11,44 @@ use std::{
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    backend::prompt::PromptProcessor,
-    data::{payload::CursorState, textrow::TextRow},
+    backend::{
+        blocks,
+        clipboard::{ClipEntry, KillRing, SystemClipboard},
+        commands,
+        history::History,
+        lines,
+        prompt::PromptProcessor,
+        syntax,
+    },
+    config::Settings,
+    data::{
+        language::Language,
+        payload::{Block, CursorState, Selection},
+        textrow::TextRow,
+    },
     gfx::render::RenderDriver,
+    utils,
 };
 
 // OperationsHandler. Its purpose in life is to manipulate the fields of a RenderDriver.
+// extra_cursors holds the positions of any cursors besides the main one (which the CursorHandler looks after), in document order.
+// holding_brackets skips looking for the bracket matching the one under the cursor, while the cursor is being moved
+// around behind the scenes (from cursor to cursor, when an event is handled at each of them).
 pub struct OperationsHandler {
     render: RenderDriver,
     file_name: String,
+    language: &'static Language,
     prompt: PromptProcessor,
-    pub prompt_matches: Vec<SearchItem>,
-    prompt_match_idx: i32,
+    prompt_matches: Vec<SearchItem>,
+    prompt_match_idx: usize,
+    last_query: Option<String>,
+    kill_ring: KillRing,
+    last_paste: Option<(Selection, (usize, usize))>,
+    last_kill: Option<(usize, usize)>,
+    system_clipboard: SystemClipboard,
+    history: History,
+    extra_cursors: Vec<(usize, usize)>,
+    holding_brackets: bool,
 }
 
 impl OperationsHandler {
@@ -30,9 +56,18 @@ impl OperationsHandler {
         Self {
             render,
             file_name: "[Untitled]".to_string(),
+            language: Language::detect(""),
             prompt: PromptProcessor::new(),
             prompt_matches: Vec::new(),
             prompt_match_idx: 0,
+            last_query: None,
+            kill_ring: KillRing::new(),
+            last_paste: None,
+            last_kill: None,
+            system_clipboard: SystemClipboard::new(),
+            history: History::new(),
+            extra_cursors: Vec::new(),
+            holding_brackets: false,
         }
     }
 
@@ -68,6 +103,32 @@ impl OperationsHandler {
                 self.render
                     .update_status_message(StatusContent::Find(s.to_string()));
                 Some(PromptResult::TextSearch(s.to_string()))
+            } else if let StatusContent::GoTo(s) = content {
+                self.render
+                    .update_status_message(StatusContent::GoTo(s.to_string()));
+                None
+            } else if let StatusContent::MacroRecord(s) = content {
+                self.render
+                    .update_status_message(StatusContent::MacroRecord(s.to_string()));
+                None
+            } else if let StatusContent::MacroPlay(s) = content {
+                self.render
+                    .update_status_message(StatusContent::MacroPlay(s.to_string()));
+                None
+            } else if let StatusContent::Filter(s) = content {
+                self.render
+                    .update_status_message(StatusContent::Filter(s.to_string()));
+                None
+            } else if let StatusContent::ShellInsert(s) = content {
+                self.render
+                    .update_status_message(StatusContent::ShellInsert(s.to_string()));
+                None
+            } else if let StatusContent::Command(s, candidates) = content {
+                self.render.update_status_message(StatusContent::Command(
+                    s.to_string(),
+                    candidates.clone(),
+                ));
+                None
             } else {
                 None
             }
@@ -97,39 +158,816 @@ impl OperationsHandler {
                 self.prompt.set_status(StatusContent::Find("".to_string()));
                 self.check_and_update_prompt_status();
             }
+            InputEvent::GoTo => {
+                self.prompt.flush();
+                self.prompt.set_status(StatusContent::GoTo("".to_string()));
+                self.check_and_update_prompt_status();
+            }
+            InputEvent::MacroRecord => {
+                self.prompt.flush();
+                self.prompt
+                    .set_status(StatusContent::MacroRecord("".to_string()));
+                self.check_and_update_prompt_status();
+            }
+            InputEvent::MacroPlay => {
+                self.prompt.flush();
+                self.prompt
+                    .set_status(StatusContent::MacroPlay("".to_string()));
+                self.check_and_update_prompt_status();
+            }
+            InputEvent::Filter => {
+                self.prompt.flush();
+                self.prompt
+                    .set_status(StatusContent::Filter("".to_string()));
+                self.check_and_update_prompt_status();
+            }
+            InputEvent::ShellInsert => {
+                self.prompt.flush();
+                self.prompt
+                    .set_status(StatusContent::ShellInsert("".to_string()));
+                self.check_and_update_prompt_status();
+            }
+            InputEvent::CommandLine => {
+                self.prompt.flush();
+                self.prompt
+                    .set_status(StatusContent::Command("".to_string(), Vec::new()));
+                self.prompt.set_completions(commands::command_names());
+                self.check_and_update_prompt_status();
+            }
             _ => (),
         }
     }
 
     // Deletes a character at the current cursor position.
     // Given direction determines whether the character before or after the cursor is deleted.
+    // Backspacing between an empty pair from the language's auto_pairs deletes the closer as well, if the pair is in code
+    // (where it would have been closed automatically) and auto_pairs is on.
     pub fn process_delete(&mut self, cursor: CursorState, d: Direction) {
-        let idx = cursor.cy as usize;
+        let (x, idx) = cursor.position();
+        let pairs = self.auto_pairs();
+        let pair_in_code = matches!(d, Direction::Left)
+            && x > 0
+            && !pairs.is_empty()
+            && syntax::code_before(self.render.get_text(), (x - 1, idx), self.language);
         let mut g = self.get_graphemes_at_line(idx);
 
         let mut target = g.len();
         match d {
-            Direction::Left => target = (cursor.cx - 1 + cursor.col_offset) as usize,
-            Direction::Right => target = (cursor.cx + cursor.col_offset) as usize,
+            Direction::Left => target = x - 1,
+            Direction::Right => target = x,
             _ => (),
         }
 
         if g.len() > target {
+            // backspacing between an empty pair (like "()") deletes both halves
+            let pair = pair_in_code
+                && g.get(target + 1).is_some_and(|next| {
+                    pairs
+                        .iter()
+                        .any(|(o, cl)| g[target] == o.to_string() && *next == cl.to_string())
+                });
+            if pair {
+                g.remove(target + 1);
+            }
             g.remove(target);
             let updated: String = g.into_iter().map(String::from).collect();
             self.render.set_text_at_index(idx, updated);
-        } else if target == (cursor.cx + cursor.col_offset).try_into().unwrap()
-            && (cursor.cy + 1) < self.render.get_text().len().try_into().unwrap()
-        {
+        } else if target == x && (idx + 1) < self.render.get_text().len() {
             self.process_wrap_delete(cursor, d);
         }
     }
 
+    // Finds the next word boundary from a position, in the given direction (left or right).
+    // Words are found with Unicode word segmentation. Whitespace is skipped over, so moving left lands on the start of the
+    // previous word, and moving right lands on the end of the next one. Runs of punctuation count as words of their own.
+    // At the start or end of a row, the boundary is the end or start of the row next to it.
+    pub fn word_boundary(&mut self, pos: (usize, usize), d: Direction) -> (usize, usize) {
+        let (x, y) = pos;
+        let rows = self.render.get_text().len();
+        // the empty line past the end of the document isn't real text, so don't create it by reading it
+        let row = if y < rows {
+            self.get_string_at_line(y).to_owned()
+        } else {
+            String::new()
+        };
+        let len = row.graphemes(true).count();
+        let x = x.min(len);
+        let words = word_spans(&row);
+
+        match d {
+            Direction::Left if x == 0 && y > 0 => (self.get_length_at_line(y - 1), y - 1),
+            Direction::Left => (
+                words
+                    .iter()
+                    .rev()
+                    .find(|(start, _)| *start < x)
+                    .map_or(0, |(start, _)| *start),
+                y,
+            ),
+            Direction::Right if x == len && y < rows => (0, y + 1),
+            Direction::Right => (
+                words
+                    .iter()
+                    .find(|(_, end)| *end > x)
+                    .map_or(len, |(_, end)| *end),
+                y,
+            ),
+            _ => pos,
+        }
+    }
+
+    // Finds the start of the next word after a position. If there are no more words on its row, this is the start of the
+    // first word on a following row. Past the last word in the document, it's the end of the last row.
+    pub fn next_word_start(&mut self, pos: (usize, usize)) -> (usize, usize) {
+        let (x, y) = pos;
+        let rows = self.render.get_text().len();
+        if y >= rows {
+            return pos;
+        }
+        let row = self.get_string_at_line(y).to_owned();
+        if let Some((start, _)) = word_spans(&row).into_iter().find(|(start, _)| *start > x) {
+            return (start, y);
+        }
+        for next in (y + 1)..rows {
+            let row = self.get_string_at_line(next).to_owned();
+            if let Some((start, _)) = word_spans(&row).first() {
+                return (*start, next);
+            }
+        }
+        (self.get_length_at_line(rows - 1), rows - 1)
+    }
+
+    // Finds the word that a position sits in (or at the end of). Returns where the word starts, along with its text.
+    pub fn word_at(&mut self, pos: (usize, usize)) -> Option<(usize, String)> {
+        let (x, y) = pos;
+        if y >= self.render.get_text().len() {
+            return None;
+        }
+        let row = self.get_string_at_line(y).to_owned();
+        let (start, end) = word_spans(&row)
+            .into_iter()
+            .find(|(start, end)| *start <= x && x <= *end)?;
+        let word: String = row.graphemes(true).skip(start).take(end - start).collect();
+        Some((start, word))
+    }
+
+    // Measures a position from the end of the document: how many rows there are below it, and how many graphemes come
+    // after it on its row. Changes made further up the document (or earlier in the row) don't affect this, so it's used to
+    // keep track of cursors while an edit is made at each of them, from the last one up.
+    // A position on the empty line past the end of the document is measured as the end of the last row.
+    pub fn offset_from_end(&mut self, pos: (usize, usize)) -> (usize, usize) {
+        let (x, y) = pos;
+        let rows = self.render.get_text().len();
+        if y >= rows {
+            return (0, 0);
+        }
+        (rows - 1 - y, self.get_length_at_line(y).saturating_sub(x))
+    }
+
+    // Finds the position that's a given offset from the end of the document. The opposite of offset_from_end.
+    pub fn position_from_end(&mut self, offset: (usize, usize)) -> (usize, usize) {
+        let (below, after) = offset;
+        let rows = self.render.get_text().len();
+        if rows == 0 {
+            return (0, 0);
+        }
+        let y = (rows - 1).saturating_sub(below);
+        let len = self.get_length_at_line(y);
+        (len.saturating_sub(after), y)
+    }
+
+    // Whether there are any cursors besides the main one.
+    pub fn has_extra_cursors(&self) -> bool {
+        !self.extra_cursors.is_empty()
+    }
+
+    // Whether there's an extra cursor at a position.
+    pub fn has_cursor_at(&self, pos: (usize, usize)) -> bool {
+        self.extra_cursors.contains(&pos)
+    }
+
+    // Adds an extra cursor at a position, unless there's one there already.
+    pub fn add_cursor(&mut self, pos: (usize, usize)) {
+        if !self.has_cursor_at(pos) {
+            self.extra_cursors.push(pos);
+            self.extra_cursors.sort_by_key(|(x, y)| (*y, *x));
+        }
+        self.render.set_extra_cursors(self.extra_cursors.clone());
+    }
+
+    // Gets rid of the extra cursor at a position, if there is one.
+    pub fn remove_cursor(&mut self, pos: (usize, usize)) {
+        self.extra_cursors.retain(|other| *other != pos);
+        self.render.set_extra_cursors(self.extra_cursors.clone());
+    }
+
+    // Gets rid of every cursor besides the main one.
+    pub fn clear_extra_cursors(&mut self) {
+        if self.has_extra_cursors() {
+            self.extra_cursors.clear();
+            self.render.set_extra_cursors(Vec::new());
+        }
+    }
+
+    // Returns every cursor, flagging the main one, from the end of the document up. Edits are made at each cursor in
+    // this order, so that making one never moves the text under a cursor that's still to come.
+    pub fn cursors_in_reverse(&self, main: (usize, usize)) -> Vec<(bool, (usize, usize))> {
+        let mut cursors: Vec<(bool, (usize, usize))> =
+            self.extra_cursors.iter().map(|pos| (false, *pos)).collect();
+        cursors.push((true, main));
+        cursors.sort_by_key(|(_, (x, y))| std::cmp::Reverse((*y, *x)));
+        cursors
+    }
+
+    // Takes where each cursor ended up after an edit was made at all of them, measured from the end of the document, and
+    // turns these back into positions. Cursors that ended up in the same place are merged, with the main cursor winning out.
+    // Returns where the main cursor is now.
+    pub fn settle_cursors(&mut self, offsets: Vec<(bool, (usize, usize))>) -> (usize, usize) {
+        let main_offset = offsets
+            .iter()
+            .find(|(main, _)| *main)
+            .map_or((0, 0), |(_, offset)| *offset);
+        let main = self.position_from_end(main_offset);
+        self.extra_cursors.clear();
+        for (is_main, offset) in offsets {
+            let pos = self.position_from_end(offset);
+            if !is_main && pos != main && !self.has_cursor_at(pos) {
+                self.extra_cursors.push(pos);
+            }
+        }
+        self.extra_cursors.sort_by_key(|(x, y)| (*y, *x));
+        self.render.set_extra_cursors(self.extra_cursors.clone());
+        main
+    }
+
+    // Deletes all of the text within a selection, joining what's left of its first and last rows together.
+    // The end of the selection might sit on the empty line past the end of the document. If the selection starts at the
+    // start of a row, it takes every row from there on, line breaks and all. If not, the end is clamped to real text.
+    pub fn delete_selection(&mut self, sel: Selection) {
+        let len = self.render.get_text().len();
+        let (sx, sy) = sel.start;
+        if sel.is_empty() || sy >= len {
+            return;
+        }
+        if sel.end.1 >= len && sx == 0 {
+            for _i in sy..len {
+                self.render.delete_row(sy);
+            }
+            return;
+        }
+        let (ex, ey) = if sel.end.1 >= len {
+            (self.get_length_at_line(len - 1), len - 1)
+        } else {
+            sel.end
+        };
+
+        let start_row = self.get_graphemes_at_line(sy);
+        let head: String = start_row[..sx.min(start_row.len())].concat();
+        let end_row = self.get_graphemes_at_line(ey);
+        let tail: String = end_row[ex.min(end_row.len())..].concat();
+
+        for _i in sy..ey {
+            self.render.delete_row(sy + 1);
+        }
+        self.render.set_text_at_index(sy, head + &tail);
+    }
+
+    // Returns the block selected by a cursor, if it's making a block selection. Its columns are measured with the file's tab width.
+    pub fn block(&mut self, cursor: CursorState) -> Option<Block> {
+        let tab_width = self.render.settings().tab_width;
+        cursor.block(self.render.get_text(), tab_width)
+    }
+
+    // Returns the text within a block, with rows joined by newlines. Rows that end before the block starts give an empty line.
+    pub fn get_block_text(&mut self, block: Block) -> String {
+        let tab_width = self.render.settings().tab_width;
+        blocks::text(self.render.get_text(), block, tab_width)
+    }
+
+    // Deletes the text within a block, from every row it covers.
+    pub fn delete_block(&mut self, block: Block) {
+        let tab_width = self.render.settings().tab_width;
+        for (y, text) in blocks::delete(self.render.get_text(), block, tab_width) {
+            self.render.set_text_at_index(y, text);
+        }
+    }
+
+    // Inserts text as a block at a position, with each line on the next row down. See blocks::insert.
+    // Returns the position just after the text inserted on the first row.
+    fn insert_block(&mut self, pos: (usize, usize), text: &str) -> (usize, usize) {
+        let tab_width = self.render.settings().tab_width;
+        let (edits, after) = blocks::insert(self.render.get_text(), pos, text, tab_width);
+        for (y, text) in edits {
+            self.render.set_text_at_index(y, text);
+        }
+        after
+    }
+
+    // Gets a block ready to be edited, by deleting its text and putting a cursor at its left edge on every row it covers.
+    // pad is whether text is about to be inserted. If so, short rows are padded out to reach the block. Otherwise, rows too
+    // short to reach it don't get a cursor, and are left alone.
+    // The cursor on main_row becomes the main cursor, and its position is returned.
+    pub fn block_to_cursors(&mut self, block: Block, main_row: usize, pad: bool) -> (usize, usize) {
+        self.delete_block(block);
+        self.clear_extra_cursors();
+        let tab_width = self.render.settings().tab_width;
+        let mut main = (0, main_row);
+        let rows = match blocks::rows(self.render.get_text(), block) {
+            Some(rows) => rows,
+            None => return main,
+        };
+        for y in rows {
+            let x = if pad {
+                let (x, padded) =
+                    blocks::pad_to_col(&self.render.get_text()[y], block.left, tab_width);
+                if let Some(text) = padded {
+                    self.render.set_text_at_index(y, text);
+                }
+                x
+            } else {
+                let row = &self.render.get_text()[y];
+                let x = row.grapheme_at_col(block.left, tab_width);
+                if x >= row.length() as usize && y != main_row {
+                    continue;
+                }
+                x.min(row.length() as usize - 1)
+            };
+            if y == main_row {
+                main = (x, y);
+            } else {
+                self.add_cursor((x, y));
+            }
+        }
+        main
+    }
+
+    // Returns all of the text within a selection, with rows joined by newlines.
+    pub fn get_selection_text(&mut self, sel: Selection) -> String {
+        let len = self.render.get_text().len();
+        let mut rows: Vec<String> = Vec::new();
+        for idx in sel.start.1..=sel.end.1.min(len.saturating_sub(1)) {
+            let g = self.get_graphemes_at_line(idx);
+            let start = if idx == sel.start.1 {
+                sel.start.0.min(g.len())
+            } else {
+                0
+            };
+            let end = if idx == sel.end.1 {
+                sel.end.0.min(g.len())
+            } else {
+                g.len()
+            };
+            rows.push(g[start..end.max(start)].concat());
+        }
+        if sel.end.1 >= len && sel.end.1 > sel.start.1 {
+            // the selection runs onto the empty line past the end of the document
+            rows.push("".to_string());
+        }
+        rows.join("\n")
+    }
+
+    // Inserts a block of text at a (column, row) position in a single operation, splitting it across as many rows as it needs.
+    // Returns the position just past the end of the inserted text.
+    pub fn insert_text(&mut self, pos: (usize, usize), text: &str) -> (usize, usize) {
+        let (x, y) = pos;
+        let g = self.get_graphemes_at_line(y);
+        let split = x.min(g.len());
+        let head: String = g[..split].concat();
+        let tail: String = g[split..].concat();
+
+        let text = text.replace("\r\n", "\n");
+        let lines: Vec<&str> = text.split('\n').collect();
+        let last = lines.len() - 1;
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            let row = if i == last {
+                format!("{}{}", line, tail)
+            } else {
+                line.to_string()
+            };
+            self.render.insert_row(y + i, TextRow::new(row));
+        }
+
+        let end_x = lines[last].graphemes(true).count();
+        if last == 0 {
+            self.render
+                .set_text_at_index(y, format!("{}{}{}", head, lines[0], tail));
+            (split + end_x, y)
+        } else {
+            self.render
+                .set_text_at_index(y, format!("{}{}", head, lines[0]));
+            (end_x, y + last)
+        }
+    }
+
+    // Copies the selection into the kill ring. If nothing is selected, the entire current line is copied instead.
+    // A selection that runs from the start of a row to the start of a later one is copied as whole lines, like the current
+    // line is. A block selection is copied as a block entry.
+    // The copied text is also sent on to the system clipboard: through the terminal (OSC 52), and through a clipboard helper if there is one.
+    pub fn copy_text(&mut self, cursor: CursorState) {
+        let block = self.block(cursor);
+        let entry = match (block, cursor.selection()) {
+            (Some(block), _) => ClipEntry::block(self.get_block_text(block)),
+            (_, Some(sel)) if !sel.is_empty() => {
+                let linewise = sel.start.0 == 0 && sel.end.0 == 0;
+                ClipEntry::new(self.get_selection_text(sel), linewise)
+            }
+            _ => {
+                let line = self.get_string_at_line(cursor.position().1).to_owned();
+                ClipEntry::new(line + "\n", true)
+            }
+        };
+        self.render.set_terminal_clipboard(&entry.text);
+        if let Err(e) = self.system_clipboard.copy(&entry.text) {
+            self.render
+                .update_status_message(StatusContent::ClipboardError(e.to_string()));
+        }
+        self.kill_ring.push(entry);
+    }
+
+    // Kills from a position to the end of its line, into the kill ring. At the end of a line, the line break is killed
+    // instead, joining the next line onto it. Kills made one after another from the same spot build up a single kill ring entry.
+    pub fn kill_line(&mut self, pos: (usize, usize)) {
+        let (x, y) = pos;
+        let rows = self.render.get_text().len();
+        if y >= rows {
+            return;
+        }
+        let len = self.get_length_at_line(y);
+        let sel = if x < len {
+            Selection::new((x, y), (len, y))
+        } else if y + 1 < rows {
+            Selection::new((len, y), (0, y + 1))
+        } else {
+            return;
+        };
+
+        let text = self.get_selection_text(sel);
+        if self.last_kill == Some(pos) {
+            self.kill_ring.append(&text);
+        } else {
+            self.kill_ring.push(ClipEntry::new(text, false));
+        }
+        let killed = self
+            .kill_ring
+            .current()
+            .map_or(String::new(), |e| e.text.to_string());
+        self.render.set_terminal_clipboard(&killed);
+        if let Err(e) = self.system_clipboard.copy(&killed) {
+            self.render
+                .update_status_message(StatusContent::ClipboardError(e.to_string()));
+        }
+        self.delete_selection(sel);
+        self.last_kill = Some(pos);
+    }
+
+    // Forgets where the last kill happened, so that the next one starts a new kill ring entry.
+    pub fn break_kill(&mut self) {
+        self.last_kill = None;
+    }
+
+    // Cuts the selection into the kill ring. If nothing is selected, the entire current line is cut instead.
+    // Cutting a block selection leaves the rows it covered in place, minus the block's text.
+    pub fn cut_text(&mut self, cursor: CursorState) {
+        self.copy_text(cursor);
+        if let Some(block) = self.block(cursor) {
+            self.delete_block(block);
+            return;
+        }
+        match cursor.selection() {
+            Some(sel) if !sel.is_empty() => self.delete_selection(sel),
+            _ => {
+                let y = cursor.position().1;
+                if self.render.get_text().len() > 1 && y < self.render.get_text().len() {
+                    self.render.delete_row(y);
+                } else {
+                    self.render.set_text_at_index(y, "".to_string());
+                }
+            }
+        }
+    }
+
+    // Pastes the newest entry in the kill ring. Line-wise entries go in above the current line, everything else goes in at the cursor.
+    // after pastes the way vim's p does instead: line-wise entries go in below the current line (with the cursor on the
+    // first of them), and everything else goes in just after the grapheme under the cursor.
+    // If there's a clipboard helper, the system clipboard is checked first -- anything new that was copied outside of gram
+    // gets added to the kill ring before pasting, so it's what ends up being pasted. Text that's already the newest entry
+    // isn't new, even if cycling has moved on to an older one.
+    // Returns where the cursor should end up, or None if there's nothing to paste.
+    pub fn paste_text(&mut self, cursor: CursorState, after: bool) -> Option<(usize, usize)> {
+        if let Some(text) = self.system_clipboard.paste() {
+            let known = self.kill_ring.newest().is_some_and(|e| e.text == text);
+            if !text.is_empty() && !known {
+                self.kill_ring.push(ClipEntry::new(text, false));
+            }
+        }
+
+        let entry = match self.kill_ring.current() {
+            Some(e) => e.clone(),
+            None => {
+                self.render
+                    .update_status_message(StatusContent::ClipboardEmpty);
+                return None;
+            }
+        };
+        let (x, y) = cursor.position();
+        if !after {
+            return Some(self.paste_entry((x, y), &entry));
+        }
+        if entry.linewise {
+            let below = (y + 1).min(self.render.get_text().len());
+            self.paste_entry((0, below), &entry);
+            return Some((0, below));
+        }
+        let len = self
+            .render
+            .get_text()
+            .get(y)
+            .map_or(0, |row| row.length() as usize - 1);
+        Some(self.paste_entry(((x + 1).min(len), y), &entry))
+    }
+
+    // Replaces the text that was just pasted with the next oldest entry in the kill ring.
+    // Only works straight after a paste -- if the cursor has moved, or the pasted text has changed, nothing happens.
+    pub fn cycle_paste(&mut self, cursor: CursorState) -> Option<(usize, usize)> {
+        let (range, after) = self.last_paste?;
+        let pasted = self.kill_ring.current()?.text.to_string();
+        if after != cursor.position() || self.get_selection_text(range) != pasted {
+            self.last_paste = None;
+            return None;
+        }
+
+        self.delete_selection(range);
+        let entry = self.kill_ring.cycle()?.clone();
+        Some(self.paste_entry(range.start, &entry))
+    }
+
+    // Inserts a kill ring entry at a (column, row) position, remembering where it went so that it can be swapped out by cycle_paste.
+    // Block entries are pasted as a block, and can't be swapped out. Neither can line-wise entries pasted past the last
+    // line: there's no line for them to go in front of, so they go after the last line's break instead.
+    fn paste_entry(&mut self, pos: (usize, usize), entry: &ClipEntry) -> (usize, usize) {
+        if entry.block {
+            self.last_paste = None;
+            return self.insert_block(pos, &entry.text);
+        }
+        let (x, y) = pos;
+        let len = self.render.get_text().len();
+        if entry.linewise && y >= len {
+            self.last_paste = None;
+            let lines = entry.text.strip_suffix('\n').unwrap_or(&entry.text);
+            let end = match len {
+                0 => self.insert_text((0, 0), lines),
+                _ => {
+                    let at = (self.get_length_at_line(len - 1), len - 1);
+                    self.insert_text(at, &format!("\n{}", lines))
+                }
+            };
+            return (x, end.1 + 1);
+        }
+        let start = if entry.linewise { (0, y) } else { (x, y) };
+        let end = self.insert_text(start, &entry.text);
+        let after = if entry.linewise { (x, end.1) } else { end };
+        self.last_paste = Some((Selection::new(start, end), after));
+        after
+    }
+
+    // Finds the bracket that matches the one under the cursor, skipping over any brackets inside strings or comments.
+    pub fn matching_bracket(&mut self, cursor: CursorState) -> Option<(usize, usize)> {
+        syntax::find_match(self.render.get_text(), cursor.position(), self.language)
+    }
+
+    // Comments out (or uncomments) every row between first and last, inclusive, using the language's comment syntax.
+    // Line comments are used when the language has them. Otherwise, each row is wrapped in a block comment.
+    // If every non-blank row is already commented, the markers are removed. If not, a marker is added at the shallowest
+    // indentation among the rows, so that the markers line up. Blank rows are left alone.
+    // Returns the column each row changed at and how many graphemes were added there (negative if removed), in order,
+    // so the caller can shift the cursor along with the text. Returns None if the language has no comment syntax.
+    pub fn toggle_line_comments(
+        &mut self,
+        first: usize,
+        last: usize,
+    ) -> Option<Vec<(usize, isize)>> {
+        let (open, close) = match (self.language.line_comment, self.language.block_comment) {
+            (Some(marker), _) => (marker, ""),
+            (None, Some((start, end))) => (start, end),
+            (None, None) => {
+                self.render
+                    .update_status_message(StatusContent::NoCommentSyntax);
+                return None;
+            }
+        };
+        let last = last.min(self.render.get_text().len().saturating_sub(1));
+        let rows: Vec<String> = (first..=last)
+            .map(|idx| self.get_string_at_line(idx).to_owned())
+            .collect();
+        let code = rows.iter().filter(|row| !row.trim().is_empty());
+        let commented = code.clone().all(|row| {
+            let t = row.trim();
+            t.len() >= open.len() + close.len() && t.starts_with(open) && t.ends_with(close)
+        });
+        let col = code
+            .map(|row| utils::leading_whitespace(row).graphemes(true).count())
+            .min()
+            .unwrap_or(0);
+
+        let mut shifts = Vec::new();
+        for (idx, row) in (first..).zip(rows) {
+            if row.trim().is_empty() {
+                shifts.push((0, 0));
+                continue;
+            }
+            let indent = utils::leading_whitespace(&row);
+            let (updated, shift) = if commented {
+                let body = &row[indent.len() + open.len()..row.trim_end().len() - close.len()];
+                let spaced = body.starts_with(' ');
+                let body = body.strip_prefix(' ').unwrap_or(body);
+                let body = if close.is_empty() {
+                    body
+                } else {
+                    body.strip_suffix(' ').unwrap_or(body)
+                };
+                let removed = open.len() + spaced as usize;
+                (format!("{}{}", indent, body), -(removed as isize))
+            } else {
+                let split = row
+                    .grapheme_indices(true)
+                    .nth(col)
+                    .map_or(row.len(), |(byte, _)| byte);
+                let tail = if close.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {}", close)
+                };
+                (
+                    format!("{}{} {}{}", &row[..split], open, &row[split..], tail),
+                    open.len() as isize + 1,
+                )
+            };
+            let at = if commented {
+                indent.graphemes(true).count()
+            } else {
+                col
+            };
+            self.render.set_text_at_index(idx, updated);
+            shifts.push((at, shift));
+        }
+        Some(shifts)
+    }
+
+    // Wraps the selected text in a block comment, or unwraps it if it's already wrapped.
+    // Only works on a selection within part of a single row -- whole rows are better off with line comments.
+    // Returns the end of the selection afterwards, or None (without doing anything) if the language has no block comments,
+    // or the selection doesn't fit.
+    pub fn toggle_block_comment(&mut self, sel: Selection) -> Option<(usize, usize)> {
+        let (start, end) = self.language.block_comment?;
+        let has_line_comments = self.language.line_comment.is_some();
+        let (sx, y) = sel.start;
+        let g = self.get_graphemes_at_line(y);
+        let ex = sel.end.0.min(g.len());
+        if sel.end.1 != y || sx >= ex {
+            return None;
+        }
+        let before = g[..sx].concat();
+        let text = g[sx..ex].concat();
+        let after = g[ex..].concat();
+        if has_line_comments && before.trim().is_empty() && after.trim().is_empty() {
+            return None;
+        }
+
+        let updated = if text.len() >= start.len() + end.len()
+            && text.starts_with(start)
+            && text.ends_with(end)
+        {
+            let inner = &text[start.len()..text.len() - end.len()];
+            let inner = inner.strip_prefix(' ').unwrap_or(inner);
+            inner.strip_suffix(' ').unwrap_or(inner).to_string()
+        } else {
+            format!("{} {} {}", start, text, end)
+        };
+        let new_end = sx + updated.graphemes(true).count();
+        self.render
+            .set_text_at_index(y, format!("{}{}{}", before, updated, after));
+        Some((new_end, y))
+    }
+
+    // Puts a copy of every row between first and last (inclusive) straight after them.
+    // Returns how many rows were copied. The empty line past the end of the document isn't copied.
+    pub fn duplicate_lines(&mut self, first: usize, last: usize) -> usize {
+        let rows = self.render.get_text().len();
+        if first >= rows {
+            return 0;
+        }
+        let last = last.min(rows - 1);
+        for (i, idx) in (first..=last).enumerate() {
+            let text = self.get_string_at_line(idx).to_owned();
+            self.render.insert_row(last + 1 + i, TextRow::new(text));
+        }
+        last - first + 1
+    }
+
+    // Moves every row between first and last (inclusive) up or down by one, by moving the row next to them to their other side.
+    // Returns whether anything moved -- rows at the top or bottom of the document have nowhere to go.
+    pub fn move_lines(&mut self, first: usize, last: usize, up: bool) -> bool {
+        let rows = self.render.get_text().len();
+        if last >= rows {
+            return false;
+        }
+        if up {
+            if first == 0 {
+                return false;
+            }
+            let above = self.get_string_at_line(first - 1).to_owned();
+            self.render.delete_row(first - 1);
+            self.render.insert_row(last, TextRow::new(above));
+        } else {
+            if last + 1 >= rows {
+                return false;
+            }
+            let below = self.get_string_at_line(last + 1).to_owned();
+            self.render.delete_row(last + 1);
+            self.render.insert_row(first, TextRow::new(below));
+        }
+        true
+    }
+
+    // Deletes every row between first and last, inclusive. The document is always left with at least one (empty) row.
+    pub fn delete_lines(&mut self, first: usize, last: usize) {
+        let rows = self.render.get_text().len();
+        if first >= rows {
+            return;
+        }
+        for _i in first..=last.min(rows - 1) {
+            self.render.delete_row(first);
+        }
+        if self.render.get_text().is_empty() {
+            self.render.insert_row(0, TextRow::new("".to_string()));
+        }
+    }
+
+    // Joins the rows after first (up to last, or just the next row) onto it. Whitespace at each join is replaced by a
+    // single space, or dropped if either side is empty. Returns the grapheme index of the last join, or None if there's
+    // nothing to join.
+    pub fn join_lines(&mut self, first: usize, last: usize) -> Option<usize> {
+        let rows = self.render.get_text().len();
+        if first + 1 >= rows {
+            return None;
+        }
+        let last = last.clamp(first + 1, rows - 1);
+        let mut joined = self.get_string_at_line(first).trim_end().to_string();
+        let mut col = 0;
+        for idx in (first + 1)..=last {
+            let next = self.get_string_at_line(idx).trim_start().to_string();
+            col = joined.graphemes(true).count();
+            if !joined.is_empty() && !next.is_empty() {
+                joined.push(' ');
+            }
+            joined.push_str(&next);
+        }
+        for _i in (first + 1)..=last {
+            self.render.delete_row(first + 1);
+        }
+        self.render.set_text_at_index(first, joined);
+        Some(col)
+    }
+
+    // Indents (or dedents) every row between first and last, inclusive.
+    // Indenting adds a level of indentation to the start of each row, matching the row's existing indent.
+    // Dedenting removes a leading tab, or up to tab_width leading spaces.
+    // Returns how many graphemes were added to (or removed from) each row, in order, so the caller can shift the cursor along with the text.
+    pub fn indent_lines(&mut self, first: usize, last: usize, dedent: bool) -> Vec<isize> {
+        let settings = self.render.settings();
+        let mut shifts = Vec::new();
+        let last = last.min(self.render.get_text().len().saturating_sub(1));
+        for idx in first..=last {
+            let row = self.get_string_at_line(idx).to_owned();
+            let (updated, shift) = if dedent {
+                let spaces = row
+                    .chars()
+                    .take(settings.tab_width)
+                    .take_while(|c| *c == ' ')
+                    .count();
+                if let Some(rest) = row.strip_prefix('\t') {
+                    (rest.to_string(), -1)
+                } else {
+                    (row[spaces..].to_string(), -(spaces as isize))
+                }
+            } else {
+                let unit = utils::indent_unit(utils::leading_whitespace(&row), settings);
+                let shift = unit.chars().count() as isize;
+                (format!("{}{}", unit, row), shift)
+            };
+            if shift != 0 {
+                self.render.set_text_at_index(idx, updated);
+            }
+            shifts.push(shift);
+        }
+        shifts
+    }
+
     // Processes a "wrapping delete". A wrapping delete is a delete that results in the deletion of an adjacent row.
     // Wrapping deletes can go forwards or backwards (from DEL or BS).
     // Operation is basically the same in both directions, but with different indices.
     pub fn process_wrap_delete(&mut self, cursor: CursorState, d: Direction) {
-        let idx = cursor.cy as usize;
+        let idx = cursor.position().1;
         let curr_row = self.get_string_at_line(idx).to_owned();
 
         // conditionally determine relevant indices
@@ -165,34 +1003,135 @@ impl OperationsHandler {
     }
 
     // Inputs a newline. Split the string at the current cursor, pushes everything ahead of the cursor to the newline.
-    pub fn process_newline(&mut self, cursor: CursorState) {
-        let y = cursor.cy as usize;
-        let x = cursor.cx as usize;
-        let str = self.get_string_at_line(y).to_owned();
-        let (left, right) = str.split_at(x);
+    // The new line copies the leading whitespace of the line it was split from, and goes one level deeper if the split
+    // line ends with one of the language's indent_after characters. Splitting between a bracket and its closer (like "{}")
+    // puts the closer on a line of its own, back at the original indentation, with the cursor on an indented line in between.
+    // Returns the column the cursor should land on, which is just past the new line's indentation.
+    pub fn process_newline(&mut self, cursor: CursorState) -> usize {
+        let (x, y) = cursor.position();
+        let g = self.get_graphemes_at_line(y);
+        let split = x.min(g.len());
+        let left: String = g[..split].concat();
+        let right: String = g[split..].concat();
 
-        self.render.set_text_at_index(y, left.to_string());
-        self.render
-            .insert_row(y + 1, TextRow::new(right.to_string()));
+        let base = utils::leading_whitespace(&left).to_string();
+        let right = right.trim_start();
+        if !self.language.indents_after(&left) {
+            let col = base.graphemes(true).count();
+            self.render.set_text_at_index(y, left);
+            self.render
+                .insert_row(y + 1, TextRow::new(format!("{}{}", base, right)));
+            return col;
+        }
+
+        let inner = format!(
+            "{}{}",
+            base,
+            utils::indent_unit(&base, self.render.settings())
+        );
+        let col = inner.graphemes(true).count();
+        self.render.set_text_at_index(y, left);
+        if right.starts_with(|c| self.language.dedent_on.contains(&c)) {
+            self.render.insert_row(y + 1, TextRow::new(inner));
+            self.render
+                .insert_row(y + 2, TextRow::new(format!("{}{}", base, right)));
+        } else {
+            self.render
+                .insert_row(y + 1, TextRow::new(format!("{}{}", inner, right)));
+        }
+        col
     }
 
     // Insert a given character at the current cursor position.
     // Graphemes are used in inserting the new character, since this is the best representation of a human-readable character in a text editor.
-    pub fn process_write(&mut self, cursor: CursorState, c: char) {
-        let idx = cursor.cy as usize;
+    // Typing one of the language's dedent_on characters with only whitespace before it removes one level of indentation first.
+    // Opening brackets and quotes are auto-closed, and typing a closer that's already next to the cursor steps over it instead.
+    // Returns the column the cursor should move to -- usually one past where it was, unless the line was dedented.
+    pub fn process_write(&mut self, cursor: CursorState, c: char) -> usize {
+        let (mut x, idx) = cursor.position();
+        if self.steps_over(cursor, c) {
+            return x + 1;
+        }
+        let closer = self.auto_closer(cursor, c);
+        let dedent = self.language.dedent_on.contains(&c);
+        let tab_width = self.render.settings().tab_width;
         let mut g = self.get_graphemes_at_line(idx);
         let mut tmp = [0u8; 4];
+        let mut closer_tmp = [0u8; 4];
         // if we are starting insertion at the very end of the line, add a space
-        if g.len() < cursor.cx.try_into().unwrap() {
+        if g.len() < x {
             g.insert(g.len(), " ");
         }
-        g.insert(
-            (cursor.cx + cursor.col_offset) as usize,
-            c.encode_utf8(&mut tmp),
-        );
+        if dedent && x > 0 && x <= g.len() && g[..x].iter().all(|s| s.trim().is_empty()) {
+            let removed = if g[0] == "\t" {
+                1
+            } else {
+                g[..x]
+                    .iter()
+                    .take(tab_width)
+                    .take_while(|s| **s == " ")
+                    .count()
+            };
+            g.drain(..removed);
+            x -= removed;
+        }
+        let at = x.min(g.len());
+        g.insert(at, c.encode_utf8(&mut tmp));
+        if let Some(closer) = closer {
+            g.insert(at + 1, closer.encode_utf8(&mut closer_tmp));
+        }
         let updated: String = g.into_iter().map(String::from).collect();
 
         self.render.set_text_at_index(idx, updated);
+        x + 1
+    }
+
+    // The language's auto_pairs, or none at all if the auto_pairs setting is off.
+    fn auto_pairs(&self) -> &'static [(char, char)] {
+        if self.render.settings().auto_pairs {
+            self.language.auto_pairs
+        } else {
+            &[]
+        }
+    }
+
+    // Whether typing a character should just step over the same character sitting right after the cursor.
+    // Only closers from the language's auto_pairs are stepped over. Brackets are only stepped over in code, but a closing
+    // quote always sits inside its string, so quotes are stepped over anywhere.
+    fn steps_over(&mut self, cursor: CursorState, c: char) -> bool {
+        let (x, y) = cursor.position();
+        let is_closer = self.auto_pairs().iter().any(|(_, cl)| *cl == c);
+        let next_matches = self
+            .get_graphemes_at_line(y)
+            .get(x)
+            .is_some_and(|g| *g == c.to_string());
+        if !is_closer || !next_matches {
+            return false;
+        }
+        self.language.quotes.contains(&c)
+            || syntax::in_code(self.render.get_text(), (x, y), self.language)
+    }
+
+    // Finds the closer to insert after a typed character, if it opens one of the language's auto_pairs.
+    // Pairs are only closed in code, and only when the cursor isn't right in front of other text.
+    // Quotes aren't closed straight after a letter or number either, so that typing an apostrophe doesn't leave a stray quote.
+    fn auto_closer(&mut self, cursor: CursorState, c: char) -> Option<char> {
+        let (x, y) = cursor.position();
+        let pairs = self.auto_pairs();
+        let (open, close) = *pairs.iter().find(|(o, _)| *o == c)?;
+        let g = self.get_graphemes_at_line(y);
+        let next_ok = g.get(x).is_none_or(|next| {
+            next.trim().is_empty() || pairs.iter().any(|(_, cl)| next.starts_with(*cl))
+        });
+        let prev_ok = open != close
+            || x == 0
+            || g.get(x - 1)
+                .is_none_or(|prev| !prev.chars().any(|ch| ch.is_alphanumeric() || ch == open));
+        if next_ok && prev_ok && syntax::in_code(self.render.get_text(), (x, y), self.language) {
+            Some(close)
+        } else {
+            None
+        }
     }
 
     // Tears down all data stored in PromptProc, and clears whatever StatusMessage is currently rendered.
@@ -204,49 +1143,355 @@ impl OperationsHandler {
                 .update_status_message(StatusContent::PromptAbort);
             self.prompt_matches.clear();
             self.prompt_match_idx = 0;
+        } else if let Some(
+            StatusContent::GoTo(_)
+            | StatusContent::Command(_, _)
+            | StatusContent::MacroRecord(_)
+            | StatusContent::MacroPlay(_)
+            | StatusContent::Filter(_)
+            | StatusContent::ShellInsert(_),
+        ) = &self.prompt.status
+        {
+            self.render
+                .update_status_message(StatusContent::PromptAbort);
         }
 
         self.prompt.flush();
     }
 
+    // Collects every match for a query in the document, in document order.
+    fn find_matches(&mut self, query: &str) -> Vec<SearchItem> {
+        let mut res: Vec<SearchItem> = Vec::new();
+        let data: &mut Vec<TextRow> = self.render.get_text();
+
+        for (i, row) in data.iter().enumerate() {
+            let indices = row.find_idx_at_substr(query);
+            for j in indices {
+                res.push(SearchItem::new(j.0, i));
+            }
+        }
+        res
+    }
+
+    // Returns the position of every match for a query, in document order. Without a query, the last confirmed search is used.
+    // Reports a miss if nothing matches.
+    pub fn match_positions(&mut self, query: Option<&str>) -> Vec<(usize, usize)> {
+        let query = match query.map(String::from).or_else(|| self.last_query.clone()) {
+            Some(q) => q,
+            None => {
+                self.render
+                    .update_status_message(StatusContent::FindMiss("".to_string()));
+                return Vec::new();
+            }
+        };
+        let matches: Vec<(usize, usize)> = self
+            .find_matches(&query)
+            .iter()
+            .map(|m| m.position())
+            .collect();
+        if matches.is_empty() {
+            self.render
+                .update_status_message(StatusContent::FindMiss(query));
+        }
+        matches
+    }
+
+    // Pushes the current search result for a query to render, and returns the match it points at (if there is one).
+    fn report_match(&mut self, query: &str, wrapped: bool) -> Option<SearchItem> {
+        let total = self.prompt_matches.len();
+        let current = if total > 0 {
+            self.prompt_match_idx + 1
+        } else {
+            0
+        };
+        self.render.update_status_message(StatusContent::FindResult(
+            query.to_string(),
+            current,
+            total,
+            wrapped,
+        ));
+        self.prompt_matches.get(self.prompt_match_idx).copied()
+    }
+
     // Processes current data collected in the prompt.
     // For a SaveAs prompt, user data should be used to set a new file name.
+    // For a Find prompt, the query is kept around so that the search can be repeated after the prompt closes.
     // Sends a PromptResult to the controler, so that it can wrap-up any other processes as needed.
     pub fn process_prompt_confirm(&mut self) -> Option<PromptResult> {
         let status = &self.prompt.status;
         if let Some(StatusContent::SaveAs(str)) = status {
             self.file_name = str.to_string();
+            self.language = Language::detect(str);
             self.render.set_file_name(str);
             self.render
                 .update_status_message(StatusContent::SaveSuccess);
             return Some(PromptResult::FileRename(str.to_string()));
+        } else if let Some(StatusContent::Find(str)) = status {
+            let query = str.to_string();
+            if self.prompt_matches.is_empty() {
+                self.render
+                    .update_status_message(StatusContent::FindMiss(query.to_string()));
+            } else {
+                self.render.update_status_message(StatusContent::FindRepeat(
+                    self.prompt_match_idx + 1,
+                    self.prompt_matches.len(),
+                    false,
+                ));
+            }
+            self.last_query = Some(query);
+            self.prompt.flush();
+            return Some(PromptResult::FindConfirm);
+        } else if let Some(StatusContent::GoTo(str)) = status {
+            let target = str.to_string();
+            if target.trim().is_empty() {
+                self.render
+                    .update_status_message(StatusContent::PromptAbort);
+            }
+            self.prompt.flush();
+            return Some(PromptResult::GoTo(target));
+        } else if let Some(StatusContent::Command(str, _)) = status {
+            let line = str.to_string();
+            self.render
+                .update_status_message(StatusContent::PromptAbort);
+            self.prompt.flush();
+            return Some(PromptResult::Command(line));
+        } else if let Some(StatusContent::MacroRecord(str) | StatusContent::MacroPlay(str)) = status
+        {
+            let text = str.to_string();
+            let res = match status {
+                Some(StatusContent::MacroRecord(_)) => PromptResult::MacroRecord(text),
+                _ => PromptResult::MacroPlay(text),
+            };
+            self.render
+                .update_status_message(StatusContent::PromptAbort);
+            self.prompt.flush();
+            return Some(res);
+        } else if let Some(StatusContent::Filter(str)) = status {
+            let cmd = str.to_string();
+            self.render
+                .update_status_message(StatusContent::PromptAbort);
+            self.prompt.flush();
+            return Some(PromptResult::Filter(cmd));
+        } else if let Some(StatusContent::ShellInsert(str)) = status {
+            let cmd = str.to_string();
+            self.render
+                .update_status_message(StatusContent::PromptAbort);
+            self.prompt.flush();
+            return Some(PromptResult::ShellInsert(cmd));
         }
         None
     }
 
-    pub fn search_text(&mut self, query: &String) -> Vec<SearchItem> {
-        let mut res: Vec<SearchItem> = Vec::new();
-        let data: &mut Vec<TextRow> = self.render.get_text();
+    // Completes the first word in the prompt, if this kind of prompt has completions. Returns whether it does.
+    pub fn complete_prompt(&mut self) -> bool {
+        if !self.prompt.has_completions() {
+            return false;
+        }
+        if self.prompt.complete() {
+            self.check_and_update_prompt_status();
+        }
+        true
+    }
 
-        for (i, row) in data.iter().enumerate() {
-            let indices = row.find_idx_at_substr(query);
-            for j in indices {
-                res.push(SearchItem::new(j.0, i));
+    // Replaces every occurrence of some text in the document. Returns how many were replaced, and says so in the status bar.
+    pub fn replace_all(&mut self, find: &str, replace: &str) -> usize {
+        let mut total = 0;
+        for idx in 0..self.render.get_text().len() {
+            let row = &self.render.get_text()[idx].raw_text;
+            let count = row.matches(find).count();
+            if count > 0 {
+                let updated = row.replace(find, replace);
+                self.render.set_text_at_index(idx, updated);
+                total += count;
+            }
+        }
+        self.render
+            .update_status_message(StatusContent::Replaced(total));
+        total
+    }
+
+    // Returns the text of the rows between first and last, inclusive. A document with no rows has no text to return.
+    pub fn line_texts(&mut self, first: usize, last: usize) -> Vec<String> {
+        let text = self.render.get_text();
+        match lines::clamp_rows(text.len(), first, last) {
+            Some(rows) => text[rows].iter().map(|row| row.raw_text.clone()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // Replaces the rows between first and last (inclusive) with new ones, which might be more or fewer of them.
+    // Only rows that end up different are touched. Reports how many rows changed, and returns that count.
+    pub fn replace_lines(&mut self, first: usize, last: usize, new: Vec<String>) -> usize {
+        let old = self.line_texts(first, last);
+        let changed = lines::count_changed(&old, &new);
+        for (i, text) in new.iter().enumerate() {
+            match old.get(i) {
+                Some(row) if row == text => (),
+                Some(_) => self.render.set_text_at_index(first + i, text.to_string()),
+                None => self
+                    .render
+                    .insert_row(first + i, TextRow::new(text.to_string())),
             }
         }
+        for _i in new.len()..old.len() {
+            self.render.delete_row(first + new.len());
+        }
+        if self.render.get_text().is_empty() {
+            self.render.insert_row(0, TextRow::new("".to_string()));
+        }
+        self.render
+            .update_status_message(StatusContent::LinesChanged(changed));
+        changed
+    }
 
-        self.prompt_matches.clone_from(&res);
-        res
+    // Changes one of the settings for the open file, and reports the result in the status bar.
+    pub fn set_option(&mut self, key: &str, value: &str) {
+        match self.render.set_setting(key, value) {
+            Ok(key) => self.render.update_status_message(StatusContent::OptionSet(
+                key.to_string(),
+                value.to_string(),
+            )),
+            Err(e) => self
+                .render
+                .update_status_message(StatusContent::CommandError(e)),
+        }
     }
 
-    // 1 = increase, -1 = decrease
-    pub fn update_prompt_match_idx(&mut self, i: i32) -> usize {
-        if (self.prompt_match_idx + i) < self.prompt_matches.len() as i32
-            && (self.prompt_match_idx + i) >= 0
-        {
-            self.prompt_match_idx += i;
+    // Shows why a command couldn't be run.
+    pub fn report_command_error(&mut self, e: String) {
+        self.render
+            .update_status_message(StatusContent::CommandError(e));
+    }
+
+    // Works out where a go-to-line target points to, given the cursor's current position (column, row).
+    // Targets can be a line number ("12"), a line and column ("12:5"), an offset from the current line ("+3" / "-3"),
+    // or a percentage of the way through the file ("50%"). Lines and columns count from 1, and are clamped to the document.
+    // Returns the target as a document position, or None if it couldn't be understood. Either way, the status bar says so.
+    pub fn resolve_goto(&mut self, target: &str, origin: (usize, usize)) -> Option<(usize, usize)> {
+        let rows = self.render.get_text().len().max(1);
+        let target = target.trim();
+        let (line, col) = match target.split_once(':') {
+            Some((line, col)) => (line, Some(col)),
+            None => (target, None),
+        };
+
+        let line = if let Some(pct) = line.strip_suffix('%') {
+            pct.parse::<usize>()
+                .ok()
+                .map(|pct| (rows * pct.min(100)).div_ceil(100).max(1) as isize)
+        } else if let Some(n) = line.strip_prefix('+') {
+            n.parse::<isize>().ok().map(|n| origin.1 as isize + 1 + n)
+        } else if let Some(n) = line.strip_prefix('-') {
+            n.parse::<isize>().ok().map(|n| origin.1 as isize + 1 - n)
+        } else {
+            line.parse::<isize>().ok()
+        };
+        let col = match col {
+            Some(col) => col.parse::<usize>().ok(),
+            None => Some(1),
+        };
+
+        match (line, col) {
+            (Some(line), Some(col)) => {
+                let y = (line.clamp(1, rows as isize) - 1) as usize;
+                let x = col.max(1) - 1;
+                self.render
+                    .update_status_message(StatusContent::GoToLine(y + 1, rows));
+                Some((x.min(self.get_length_at_line(y)), y))
+            }
+            _ => {
+                self.render
+                    .update_status_message(StatusContent::GoToInvalid(target.to_string()));
+                None
+            }
+        }
+    }
+
+    // Searches the document for a query, picking the first match at or after the given origin (column, row).
+    // If there are no matches past the origin, the search wraps around to the first match in the document.
+    pub fn search_text(&mut self, query: &str, origin: (usize, usize)) -> Option<SearchItem> {
+        self.prompt_matches = self.find_matches(query);
+        let next = self
+            .prompt_matches
+            .iter()
+            .position(|m| (m.cy as usize, m.cx as usize) >= (origin.1, origin.0));
+        let wrapped = next.is_none() && !self.prompt_matches.is_empty();
+        self.prompt_match_idx = next.unwrap_or(0);
+        self.report_match(query, wrapped)
+    }
+
+    // Steps through the current search results while the prompt is open. 1 = next match, -1 = previous match.
+    // Stepping past either end of the results wraps around to the other end.
+    pub fn update_prompt_match_idx(&mut self, i: i32) -> Option<SearchItem> {
+        let query = match &self.prompt.status {
+            Some(StatusContent::Find(q)) => q.to_string(),
+            _ => return None,
+        };
+        let len = self.prompt_matches.len() as i32;
+        if len == 0 {
+            return self.report_match(&query, false);
+        }
+
+        let next = self.prompt_match_idx as i32 + i;
+        let wrapped = next < 0 || next >= len;
+        self.prompt_match_idx = next.rem_euclid(len) as usize;
+        self.report_match(&query, wrapped)
+    }
+
+    // Repeats the last confirmed search from the current cursor position, after the prompt has been closed.
+    // Searching forward picks the first match after the cursor, backward picks the last match before it.
+    // Either direction wraps around the document if there's nothing left to find.
+    pub fn repeat_search(&mut self, cursor: CursorState, forward: bool) -> Option<SearchItem> {
+        let query = match &self.last_query {
+            Some(q) => q.to_string(),
+            None => {
+                self.render
+                    .update_status_message(StatusContent::FindMiss("".to_string()));
+                return None;
+            }
+        };
+        self.prompt_matches = self.find_matches(&query);
+        if self.prompt_matches.is_empty() {
+            self.render
+                .update_status_message(StatusContent::FindMiss(query));
+            return None;
         }
-        self.prompt_match_idx as usize
+        self.render.highlight_search(Some(query));
+
+        let (x, y) = cursor.position();
+        let here = (y, x);
+        let (idx, wrapped) = if forward {
+            match self
+                .prompt_matches
+                .iter()
+                .position(|m| (m.cy as usize, m.cx as usize) > here)
+            {
+                Some(i) => (i, false),
+                None => (0, true),
+            }
+        } else {
+            match self
+                .prompt_matches
+                .iter()
+                .rposition(|m| (m.cy as usize, m.cx as usize) < here)
+            {
+                Some(i) => (i, false),
+                None => (self.prompt_matches.len() - 1, true),
+            }
+        };
+
+        self.prompt_match_idx = idx;
+        self.render.update_status_message(StatusContent::FindRepeat(
+            idx + 1,
+            self.prompt_matches.len(),
+            wrapped,
+        ));
+        self.prompt_matches.get(idx).copied()
+    }
+
+    // Clears any search matches highlighted on screen. The last query is kept, so the search can still be repeated.
+    pub fn clear_search_highlight(&mut self) {
+        self.render.highlight_search(None);
     }
 
     // Processes writes to the prompt. It's very similar to processing writes to render,
@@ -321,7 +1566,24 @@ impl OperationsHandler {
     // If a file name was set (i.e. arg mode), data is written to the modified file.
     // If a file name was not set, data is written to a new file. Filler file name for now.
     // After the file is written, update RenderDriver's status message to reflect the successful disk write.
-    pub fn write_file(&mut self, name: &str) {
+    // If it can't be written (say, its directory doesn't exist), the error is shown in the status bar instead, and returned.
+    pub fn write_file(&mut self, name: &str) -> Result<(), Error> {
+        let result = self.write_to_disk(name);
+        match &result {
+            Ok(()) => {
+                self.history.mark_saved();
+                self.render
+                    .update_status_message(StatusContent::SaveSuccess);
+            }
+            Err(e) => self
+                .render
+                .update_status_message(StatusContent::SaveFailed(e.to_string())),
+        }
+        result
+    }
+
+    // Does the writing for write_file.
+    fn write_to_disk(&mut self, name: &str) -> Result<(), Error> {
         if !self.render.is_quitting() {
             let data: &Vec<TextRow> = self.render.get_text();
             let mut output = String::from("");
@@ -339,19 +1601,14 @@ impl OperationsHandler {
                     ""
                 };
                 let path = format!("{}{}", name, extension);
-                f = File::create(path).unwrap();
+                f = File::create(path)?;
             } else {
-                f = OpenOptions::new()
-                    .write(true)
-                    .truncate(true)
-                    .open(name)
-                    .unwrap();
+                f = OpenOptions::new().write(true).truncate(true).open(name)?;
             }
 
-            f.write_all(output.as_bytes()).unwrap();
-            self.render
-                .update_status_message(StatusContent::SaveSuccess);
+            f.write_all(output.as_bytes())?;
         }
+        Ok(())
     }
 
     pub fn check_file_name(&mut self) -> bool {
@@ -365,19 +1622,118 @@ impl OperationsHandler {
     }
 
     // Wrapper around RenderDriver's set_text.
+    // A freshly opened file has nothing to undo, and only the one cursor.
     pub fn set_text(&mut self, text: Vec<TextRow>) {
         self.render.set_text(text);
+        self.history.clear(self.render.get_text());
+        self.clear_extra_cursors();
     }
 
     // Wrapper around RenderDriver's update_cursor_state.
+    // Also looks for a bracket matching the one under the cursor, so that the pair can be highlighted.
     pub fn update_cursor_state(&mut self, state: CursorState) {
+        if !self.holding_brackets {
+            let pos = state.position();
+            let pair = self.matching_bracket(state).map(|other| [pos, other]);
+            self.render.highlight_brackets(pair);
+        }
         self.render.update_cursor_state(state);
     }
 
+    // Stops (or starts again) looking for matching brackets as the cursor moves.
+    pub fn hold_brackets(&mut self, hold: bool) {
+        self.holding_brackets = hold;
+    }
+
     // Wrapper around RenderDriver's set_file_name.
     pub fn set_file_name(&mut self, name: &str) {
         self.render.set_file_name(name);
         self.file_name = name.to_string();
+        self.language = Language::detect(name);
+    }
+
+    // Wrapper around RenderDriver's gutter_width.
+    pub fn gutter_width(&self) -> u16 {
+        self.render.gutter_width()
+    }
+
+    // Wrapper around RenderDriver's settings.
+    pub fn settings(&self) -> Settings {
+        self.render.settings()
+    }
+
+    // Takes an undo checkpoint before a change. Changes of the same merge kind in a row share a single checkpoint.
+    pub fn checkpoint(&mut self, cursor: (usize, usize), merge: Option<&'static str>) {
+        self.history
+            .checkpoint(merge, self.render.get_text(), cursor);
+    }
+
+    // Stops the next change from sharing the last change's checkpoint.
+    pub fn break_merge(&mut self) {
+        self.history.break_merge();
+    }
+
+    // Starts a group of changes that are undone all at once.
+    pub fn begin_group(&mut self, cursor: (usize, usize)) {
+        self.history.begin_group(self.render.get_text(), cursor);
+    }
+
+    // Ends a group of changes.
+    pub fn end_group(&mut self) {
+        self.history.end_group();
+    }
+
+    // Undoes (or redoes) the last change, putting the document back the way it was. Coming back to the version of the
+    // document that was last saved leaves it with no unsaved changes.
+    // Returns where the cursor was at the time, or None if there's nothing to undo.
+    pub fn undo(&mut self, cursor: (usize, usize), redo: bool) -> Option<(usize, usize)> {
+        let text = self.render.get_text();
+        let restored = if redo {
+            self.history.redo(text, cursor)
+        } else {
+            self.history.undo(text, cursor)
+        };
+        let restore = match restored {
+            Some(restore) => restore,
+            None => {
+                self.render
+                    .update_status_message(StatusContent::UndoEmpty(redo));
+                return None;
+            }
+        };
+        let rows = restore.rows.into_iter().map(TextRow::new).collect();
+        self.render.restore_rows(
+            restore.start..restore.start + restore.remove,
+            rows,
+            !self.history.is_saved(),
+        );
+        self.last_paste = None;
+        Some(restore.cursor)
+    }
+
+    // Wrapper around RenderDriver's set_macro_label.
+    pub fn set_macro_label(&mut self, label: Option<String>) {
+        self.render.set_macro_label(label);
+    }
+
+    // Wrapper around RenderDriver's update_status_message, for reporting on macros.
+    pub fn report_macro(&mut self, status: StatusContent) {
+        self.render.update_status_message(status);
+    }
+
+    // Wrapper around RenderDriver's update_status_message, for reporting on shell commands run in the background.
+    pub fn report_shell(&mut self, status: StatusContent) {
+        self.render.update_status_message(status);
+    }
+
+    // Wrapper around RenderDriver's set_mode_label.
+    pub fn set_mode_label(&mut self, label: Option<String>) {
+        self.render.set_mode_label(label);
+    }
+
+    // Wrapper around RenderDriver's is_dirty.
+    pub fn is_dirty(&self) -> bool {
+        self.render.is_dirty()
     }
 
     // Wrapper around RenderDriver's tick_screen.
@@ -391,8 +1747,31 @@ impl OperationsHandler {
     }
 
     // Wrapper around RenderDriver's complete_init.
+    // Undo history starts from the document as it is now.
     pub fn complete_init(&mut self) {
         self.render.complete_init();
+        self.history.clear(self.render.get_text());
+    }
+
+    // Wrapper around RenderDriver's update_status_message, for reporting problems with config files.
+    pub fn report_config_errors(&mut self, errors: Vec<String>) {
+        self.render
+            .update_status_message(StatusContent::ConfigError(errors));
     }
     // END OF WRAPPER METHODS //
 }
+
+// Returns the start and end of each word in a row, as grapheme indices.
+// Words are found with Unicode word segmentation, and whitespace between them is left out.
+fn word_spans(row: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut col = 0;
+    for segment in row.split_word_bounds() {
+        let width = segment.graphemes(true).count();
+        if !segment.trim().is_empty() {
+            words.push((col, col + width));
+        }
+        col += width;
+    }
+    words
+}
@@ -1,12 +1,16 @@
 use crate::data::{enums::StatusContent, textrow::TextRow};
+use unicode_segmentation::UnicodeSegmentation;
 
 // PromptProcessor. The PromptProcessor can be thought of as a highly stripped down version of the RenderEngine.
 // It carries a single TextRow to contain any user input, cx corresponding to the cursor in the prompt field, and StatusContent to represent
 // what kind of prompt interaction is in-progress. The fields of the PromptProc should be primarily manipulated by the OperationsHandler.
+// Any kind of prompt can be given a list of completions. The first word of the input is fuzzy-matched against them,
+// and can be completed to the best match.
 pub struct PromptProcessor {
     pub text: TextRow,
     pub status: Option<StatusContent>,
     pub cx: i16,
+    completions: Vec<&'static str>,
 }
 
 impl PromptProcessor {
@@ -15,21 +19,28 @@ impl PromptProcessor {
             text: TextRow::new("".to_string()),
             status: None,
             cx: 0,
+            completions: Vec::new(),
         }
     }
 
     // Updates status content according to any user input that has been received so far.
     fn update_status_content(&mut self) {
-        let status = &self.status;
-        if let Some(content) = status {
-            if let StatusContent::SaveAs(_str) = content {
-                let new_status = StatusContent::SaveAs(self.text.raw_text.clone());
-                self.set_status(new_status);
-            } else if let StatusContent::Find(_str) = content {
-                let new_status = StatusContent::Find(self.text.raw_text.clone());
-                self.set_status(new_status);
+        let text = self.text.raw_text.clone();
+        let new_status = match &self.status {
+            Some(StatusContent::SaveAs(_)) => StatusContent::SaveAs(text),
+            Some(StatusContent::Find(_)) => StatusContent::Find(text),
+            Some(StatusContent::GoTo(_)) => StatusContent::GoTo(text),
+            Some(StatusContent::MacroRecord(_)) => StatusContent::MacroRecord(text),
+            Some(StatusContent::MacroPlay(_)) => StatusContent::MacroPlay(text),
+            Some(StatusContent::Filter(_)) => StatusContent::Filter(text),
+            Some(StatusContent::ShellInsert(_)) => StatusContent::ShellInsert(text),
+            Some(StatusContent::Command(_, _)) => {
+                let candidates = self.candidates().iter().map(|c| c.to_string()).collect();
+                StatusContent::Command(text, candidates)
             }
-        }
+            _ => return,
+        };
+        self.set_status(new_status);
     }
 
     // Flushes all data within the processor, resetting to default values.
@@ -37,6 +48,7 @@ impl PromptProcessor {
         self.cx = 0;
         self.text = TextRow::new("".to_string());
         self.status = None;
+        self.completions.clear();
     }
 
     // Set the cursor.
@@ -49,6 +61,16 @@ impl PromptProcessor {
         self.status = Some(status);
     }
 
+    // Set the list of words that the first word of the input can be completed to.
+    pub fn set_completions(&mut self, completions: Vec<&'static str>) {
+        self.completions = completions;
+    }
+
+    // Whether this prompt has anything to complete to.
+    pub fn has_completions(&self) -> bool {
+        !self.completions.is_empty()
+    }
+
     // Set text. If there's an ongoing prompt interaction, update status content.
     pub fn set_text(&mut self, text: String) {
         self.text.update_text(text);
@@ -57,4 +79,50 @@ impl PromptProcessor {
             _ => self.update_status_content(),
         }
     }
+
+    // Returns the completions that the first word of the input could complete to, best match first.
+    // Once the first word is finished (there's a space after it), or if it's empty, there's nothing to complete.
+    pub fn candidates(&self) -> Vec<&'static str> {
+        let word = &self.text.raw_text;
+        if word.is_empty() || word.contains(' ') {
+            return Vec::new();
+        }
+        let mut scored: Vec<((bool, usize), &'static str)> = self
+            .completions
+            .iter()
+            .filter_map(|c| fuzzy_score(word, c).map(|score| (score, *c)))
+            .collect();
+        scored.sort_by_key(|(score, c)| (*score, c.len()));
+        scored.into_iter().map(|(_, c)| c).collect()
+    }
+
+    // Replaces the first word of the input with its best completion, and moves the cursor to the end.
+    // Returns whether there was anything to complete to.
+    pub fn complete(&mut self) -> bool {
+        let best = match self.candidates().first() {
+            Some(best) => best.to_string(),
+            None => return false,
+        };
+        self.set_text(best);
+        self.cx = self.text.raw_text.graphemes(true).count() as i16;
+        true
+    }
+}
+
+// Scores how well a query fuzzy-matches a word, or returns None if it doesn't match at all.
+// The query's characters have to appear in the word in order, but not necessarily next to each other.
+// Lower scores are better: a match that starts at the beginning of the word beats any that doesn't, and after that,
+// the fewer characters skipped over in the word, the better.
+fn fuzzy_score(query: &str, word: &str) -> Option<(bool, usize)> {
+    let mut chars = word.chars().enumerate();
+    let mut first = None;
+    let mut last = 0;
+    for q in query.chars() {
+        let (i, _) = chars.find(|(_, c)| c.eq_ignore_ascii_case(&q))?;
+        first.get_or_insert(i);
+        last = i;
+    }
+    let first = first.unwrap_or(0);
+    let skipped = last + 1 - first - query.chars().count();
+    Some((first != 0, skipped))
 }
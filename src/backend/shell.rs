@@ -0,0 +1,185 @@
+use std::{
+    io::{Read, Write},
+    os::unix::process::CommandExt,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Functions for running shell commands.
+
+// Runs a shell command with some text on its stdin, and returns what it wrote to stdout.
+// Fails if the command can't be started, exits with an error, or hasn't finished after FILTER_TIMEOUT. It's only finished
+// once its output pipes are closed too, which anything it started in the background can keep open. The command runs in a
+// process group of its own, so that on a timeout the whole group is killed.
+// The error is the first line the command wrote to stderr, if it wrote anything.
+pub fn filter(cmd: &str, input: &str) -> Result<String, String> {
+    let mut child = Command::new(SHELL)
+        .args(["-c", cmd])
+        .process_group(0)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("couldn't start {}: {}", SHELL, e))?;
+
+    // stdin, stdout and stderr each get a thread, so that a command with a lot to say can't fill up a pipe and stall
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_string();
+    thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let stdout = read_in_background(child.stdout.take().unwrap());
+    let stderr = read_in_background(child.stderr.take().unwrap());
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) if stdout.is_finished() && stderr.is_finished() => break status,
+            Ok(_) if start.elapsed() >= FILTER_TIMEOUT => {
+                kill_group(&mut child);
+                return Err(format!("timed out after {}s", FILTER_TIMEOUT.as_secs()));
+            }
+            Ok(_) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let reason = match stderr.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) => line.trim().to_string(),
+            None => status.to_string(),
+        };
+        return Err(reason);
+    }
+    Ok(stdout)
+}
+
+// ShellJob. A shell command running in the background, whose output is being captured.
+// child is the running command, and cmd is the command line it was started with.
+// stdout and stderr are read on their own threads. stdout stops being read after OUTPUT_LIMIT bytes.
+pub struct ShellJob {
+    child: Child,
+    cmd: String,
+    stdout: Option<thread::JoinHandle<Vec<u8>>>,
+    stderr: Option<thread::JoinHandle<String>>,
+    captured: Option<Vec<u8>>,
+}
+
+impl ShellJob {
+    // Starts running a shell command. It gets nothing on its stdin, so commands that read from it don't wait forever.
+    // Like a filter, it runs in a process group of its own, so that stopping it stops anything it started too.
+    // Fails if the command can't be started.
+    pub fn start(cmd: &str) -> Result<Self, String> {
+        let mut child = Command::new(SHELL)
+            .args(["-c", cmd])
+            .process_group(0)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("couldn't start {}: {}", SHELL, e))?;
+
+        let mut stdout = child.stdout.take().unwrap().take(OUTPUT_LIMIT as u64 + 1);
+        let stdout = thread::spawn(move || {
+            let mut bytes = Vec::new();
+            let _ = stdout.read_to_end(&mut bytes);
+            bytes
+        });
+        let stderr = read_in_background(child.stderr.take().unwrap());
+        Ok(Self {
+            child,
+            cmd: cmd.to_string(),
+            stdout: Some(stdout),
+            stderr: Some(stderr),
+            captured: None,
+        })
+    }
+
+    // The command line the job was started with.
+    pub fn command(&self) -> &str {
+        &self.cmd
+    }
+
+    // Checks on the command without waiting for it. Returns None while it's still running, or while it (or anything it
+    // left running in the background) still has its output pipes open.
+    // Once it's done, returns what it wrote to stdout, and whether that was cut off at OUTPUT_LIMIT bytes. A command that
+    // says more than that is stopped, since nothing else it says would be kept.
+    // Fails if the command exits with an error, in which case the error is the first line it wrote to stderr.
+    pub fn poll(&mut self) -> Option<Result<(String, bool), String>> {
+        if self.stdout.as_ref().is_some_and(|t| t.is_finished()) {
+            self.captured = self.stdout.take().map(|t| t.join().unwrap_or_default());
+        }
+        if self.captured.as_ref()?.len() > OUTPUT_LIMIT {
+            kill_group(&mut self.child);
+            // stderr is no longer needed, and its thread finishes on its own now that the command is gone
+            self.stderr = None;
+            let mut bytes = self.captured.take()?;
+            bytes.truncate(OUTPUT_LIMIT);
+            return Some(Ok((String::from_utf8_lossy(&bytes).into_owned(), true)));
+        }
+        let status = match self.child.try_wait() {
+            Ok(Some(status)) => status,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e.to_string())),
+        };
+        if !self.stderr.as_ref()?.is_finished() {
+            return None;
+        }
+
+        let stderr = self.stderr.take()?.join().unwrap_or_default();
+        let bytes = self.captured.take()?;
+        if !status.success() {
+            let reason = match stderr.lines().find(|line| !line.trim().is_empty()) {
+                Some(line) => line.trim().to_string(),
+                None => status.to_string(),
+            };
+            return Some(Err(reason));
+        }
+        Some(Ok((String::from_utf8_lossy(&bytes).into_owned(), false)))
+    }
+}
+
+// A job that's dropped before it finishes (because it was cancelled, or the editor is closing) stops its command, and
+// anything the command started.
+impl Drop for ShellJob {
+    fn drop(&mut self) {
+        let running = matches!(self.child.try_wait(), Ok(None));
+        if running || self.stdout.is_some() || self.stderr.is_some() {
+            kill_group(&mut self.child);
+        }
+    }
+}
+
+// Kills a command that was started in a process group of its own, along with everything else in the group (like anything
+// it left running in the background). Then waits for the command, so that it isn't left behind as a zombie.
+fn kill_group(child: &mut Child) {
+    let _ = Command::new(SHELL)
+        .args(["-c", &format!("kill -KILL -{}", child.id())])
+        .stderr(Stdio::null())
+        .status();
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+// Reads everything from a pipe on another thread. Anything that isn't valid UTF-8 is replaced.
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+// CONSTS //
+
+const SHELL: &str = "sh";
+
+// How long a filter command can run before it's given up on.
+const FILTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How much of a command's output is kept, in bytes, when inserting it into the text.
+const OUTPUT_LIMIT: usize = 1024 * 1024;
+
+// How often a running command is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
@@ -0,0 +1,155 @@
+use crate::data::{language::Language, textrow::TextRow};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Functions for picking out the code in a document, as opposed to its strings and comments.
+// Used for finding matching brackets (where anything inside a string or a comment is skipped over), and for deciding
+// whether it's worth auto-closing a bracket or quote as it's typed.
+
+// Finds the bracket that matches the one at pos (a grapheme index and a row index), if there is one.
+// Scans outward from pos, counting nested brackets of the same kind, up to SCAN_ROWS rows away (well off-screen, but it
+// keeps the cost of moving the cursor the same in a huge file).
+// Returns None if pos isn't on a bracket, the bracket is inside a string or comment, or it's unbalanced.
+pub fn find_match(
+    data: &[TextRow],
+    pos: (usize, usize),
+    lang: &Language,
+) -> Option<(usize, usize)> {
+    let (x, y) = pos;
+    let c = data.get(y)?.raw_text.graphemes(true).nth(x)?;
+    let (other, forward) = match BRACKET_PAIRS.iter().find(|(o, cl)| c == *o || c == *cl) {
+        Some((o, cl)) if c == *o => (*cl, true),
+        Some((o, _)) => (*o, false),
+        None => return None,
+    };
+
+    let first = y.saturating_sub(SCAN_ROWS);
+    let last = (y + SCAN_ROWS).min(data.len() - 1);
+    let brackets = code_brackets(data, first, last, lang);
+    let idx = brackets.iter().position(|b| b.0 == x && b.1 == y)?;
+    let mut depth = 0;
+    let mut closes = |b: &&(usize, usize, &str)| {
+        if b.2 == c {
+            depth += 1;
+        } else if b.2 == other {
+            depth -= 1;
+        }
+        depth == 0
+    };
+    let found = if forward {
+        brackets[idx..].iter().find(&mut closes)
+    } else {
+        brackets[..=idx].iter().rev().find(&mut closes)
+    };
+    found.map(|b| (b.0, b.1))
+}
+
+// Whether a position in the document (a grapheme index and a row index) is part of the code, rather than a string or comment.
+// A position at the end of a line counts as code unless a string or comment is still open there.
+// Only the SCAN_ROWS rows above pos are looked at, so a block comment that opened further up than that is missed.
+pub fn in_code(data: &[TextRow], pos: (usize, usize), lang: &Language) -> bool {
+    let (x, y) = pos;
+    let mut in_block = false;
+    for row in data.iter().take(y).skip(y.saturating_sub(SCAN_ROWS)) {
+        code_mask(&row.raw_text, lang, &mut in_block);
+    }
+    match data.get(y) {
+        Some(row) => {
+            let mask = code_mask(&row.raw_text, lang, &mut in_block);
+            mask[x.min(mask.len() - 1)]
+        }
+        None => !in_block,
+    }
+}
+
+// Whether the text just before a position (a grapheme index and a row index) is code, so that anything starting at pos
+// would start in code. Unlike in_code, the grapheme at pos doesn't count: a quote there is in code if it opens a string.
+pub fn code_before(data: &[TextRow], pos: (usize, usize), lang: &Language) -> bool {
+    let (x, y) = pos;
+    let mut in_block = false;
+    for row in data.iter().take(y).skip(y.saturating_sub(SCAN_ROWS)) {
+        code_mask(&row.raw_text, lang, &mut in_block);
+    }
+    match data.get(y) {
+        Some(row) => {
+            let head: String = row.raw_text.graphemes(true).take(x).collect();
+            let mask = code_mask(&head, lang, &mut in_block);
+            mask[mask.len() - 1]
+        }
+        None => !in_block,
+    }
+}
+
+// Lists every bracket between rows first and last (inclusive) that's part of the code, in document order.
+// Each bracket is given as its grapheme index, row index, and the bracket itself.
+fn code_brackets<'a>(
+    data: &'a [TextRow],
+    first: usize,
+    last: usize,
+    lang: &Language,
+) -> Vec<(usize, usize, &'a str)> {
+    let mut brackets = Vec::new();
+    let mut in_block = false;
+    for (y, row) in data.iter().enumerate().take(last + 1).skip(first) {
+        let mask = code_mask(&row.raw_text, lang, &mut in_block);
+        for (x, g) in row.raw_text.graphemes(true).enumerate() {
+            if mask[x] && BRACKET_PAIRS.iter().any(|(o, c)| g == *o || g == *c) {
+                brackets.push((x, y, g));
+            }
+        }
+    }
+    brackets
+}
+
+// Works out which graphemes in a line of text are part of the code. Strings, comments, and the quotes or markers
+// around them are not. The mask has one more entry than the line has graphemes, for the position at the end of the line.
+// Strings are tracked per line, and a backslash escapes the next character. in_block carries a block comment over from
+// one line to the next.
+fn code_mask(text: &str, lang: &Language, in_block: &mut bool) -> Vec<bool> {
+    let graphemes: Vec<(usize, &str)> = text.grapheme_indices(true).collect();
+    let mut mask = vec![false; graphemes.len() + 1];
+    let mut in_string: Option<&str> = None;
+    let mut x = 0;
+    while x < graphemes.len() {
+        let (byte, g) = graphemes[x];
+        let rest = &text[byte..];
+        if *in_block {
+            if let Some((_, end)) = lang.block_comment.filter(|(_, end)| rest.starts_with(end)) {
+                *in_block = false;
+                x += end.len();
+                continue;
+            }
+        } else if let Some(quote) = in_string {
+            if g == "\\" {
+                x += 1;
+            } else if g == quote {
+                in_string = None;
+            }
+        } else if lang
+            .line_comment
+            .is_some_and(|start| rest.starts_with(start))
+        {
+            return mask;
+        } else if let Some((start, _)) = lang
+            .block_comment
+            .filter(|(start, _)| rest.starts_with(start))
+        {
+            *in_block = true;
+            x += start.len();
+            continue;
+        } else if lang.quotes.iter().any(|q| g.starts_with(*q)) {
+            in_string = Some(g);
+        } else {
+            mask[x] = true;
+        }
+        x += 1;
+    }
+    mask[graphemes.len()] = !*in_block && in_string.is_none();
+    mask
+}
+
+// CONSTS //
+
+const BRACKET_PAIRS: &[(&str, &str)] = &[("(", ")"), ("[", "]"), ("{", "}")];
+
+// How many rows either side of a position are looked at, when working out what's code around it.
+const SCAN_ROWS: usize = 1000;
@@ -0,0 +1,207 @@
+use crate::{
+    gfx::theme::{Theme, DEFAULT_THEME},
+    utils,
+};
+use std::{collections::HashMap, fs, path::Path};
+use toml::{Table, Value};
+
+// Functions for loading editor settings from the user's config file.
+
+// Settings. The settings that can be changed for each filetype.
+// tab_width: how many columns a level of indentation takes up.
+// soft_tabs: whether the Tab key (and new indentation) uses spaces instead of a tab character.
+// line_numbers: whether a gutter with line numbers is drawn to the left of the text.
+// auto_pairs: whether brackets and quotes are closed automatically as they're typed (for languages that have pairs).
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub tab_width: usize,
+    pub soft_tabs: bool,
+    pub line_numbers: bool,
+    pub auto_pairs: bool,
+}
+
+// Config. Everything that can be set in config.toml.
+// settings holds the defaults for every file, and filetypes holds the settings for any file extension with its own section.
+// theme: the colors used for syntax highlighting and highlights.
+// autosave_interval: how many seconds to wait between automatic saves of a modified file. 0 turns autosave off.
+// status_timeout: how many seconds a status message stays up for.
+// dirty_quit_count: how many extra times Ctrl+Q has to be pressed to quit with unsaved changes.
+#[derive(Clone)]
+pub struct Config {
+    pub settings: Settings,
+    pub theme: &'static Theme,
+    pub autosave_interval: u64,
+    pub status_timeout: u64,
+    pub dirty_quit_count: i16,
+    filetypes: HashMap<String, Settings>,
+}
+
+impl Config {
+    // Builds a Config with every setting at its default.
+    pub fn new() -> Self {
+        Self {
+            settings: Settings {
+                tab_width: 4,
+                soft_tabs: true,
+                line_numbers: false,
+                auto_pairs: true,
+            },
+            theme: DEFAULT_THEME,
+            autosave_interval: 0,
+            status_timeout: 5,
+            dirty_quit_count: 3,
+            filetypes: HashMap::new(),
+        }
+    }
+
+    // Builds a Config out of the user's config file, if there is one. Anything the file doesn't set keeps its default.
+    // Also returns a list of problems with the file, so that they can be reported at startup. Bad settings are skipped.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut config = Self::new();
+        let mut errors = Vec::new();
+        let path = match utils::config_path(CONFIG_FILE) {
+            Some(path) if path.exists() => path,
+            _ => return (config, errors),
+        };
+
+        match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| s.parse::<Table>().map_err(|e| e.message().to_string()))
+        {
+            Ok(table) => config.apply(&table, &mut errors),
+            Err(e) => errors.push(e),
+        }
+        let errors = errors
+            .into_iter()
+            .map(|e| format!("{}: {}", CONFIG_FILE, e))
+            .collect();
+        (config, errors)
+    }
+
+    // Applies every setting in a parsed config file. Global settings are applied first, so that filetype sections
+    // start out from them no matter where they appear in the file.
+    fn apply(&mut self, table: &Table, errors: &mut Vec<String>) {
+        for (key, value) in table {
+            if key == "filetype" {
+                continue;
+            }
+            if let Err(e) = self.apply_setting(key, value) {
+                errors.push(e);
+            }
+        }
+
+        match table.get("filetype") {
+            None => (),
+            Some(Value::Table(filetypes)) => {
+                for (ext, section) in filetypes {
+                    let section = match section {
+                        Value::Table(section) => section,
+                        _ => {
+                            errors.push(format!("[filetype.{}] should be a section", ext));
+                            continue;
+                        }
+                    };
+                    let mut settings = self.settings;
+                    for (key, value) in section {
+                        if let Err(e) = apply_file_setting(&mut settings, key, value) {
+                            errors.push(format!("[filetype.{}] {}", ext, e));
+                        }
+                    }
+                    self.filetypes.insert(ext.to_string(), settings);
+                }
+            }
+            Some(_) => errors.push("filetype should hold sections, like [filetype.py]".to_string()),
+        }
+    }
+
+    // Applies a single top-level setting.
+    fn apply_setting(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        match key {
+            "theme" => {
+                let name = value
+                    .as_str()
+                    .ok_or_else(|| "theme should be a string".to_string())?;
+                self.theme = Theme::named(name).ok_or_else(|| {
+                    format!(
+                        "unknown theme \"{}\" (try {})",
+                        name,
+                        Theme::names().join(", ")
+                    )
+                })?;
+            }
+            "autosave_interval" => self.autosave_interval = whole_number(key, value, 0, 86400)?,
+            "status_timeout" => self.status_timeout = whole_number(key, value, 1, 3600)?,
+            "dirty_quit_count" => self.dirty_quit_count = whole_number(key, value, 0, 100)? as i16,
+            _ => apply_file_setting(&mut self.settings, key, value)?,
+        }
+        Ok(())
+    }
+
+    // Returns the settings for a file, using the section for its extension if there is one.
+    pub fn for_file(&self, file_name: &str) -> Settings {
+        Path::new(file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.filetypes.get(ext))
+            .copied()
+            .unwrap_or(self.settings)
+    }
+}
+
+// Changes a single setting that can be set per filetype, from text typed on the command line (like "tabwidth" and "4").
+// Setting names can be written with or without underscores. Returns the setting's full name.
+pub fn set_from_text(
+    settings: &mut Settings,
+    key: &str,
+    value: &str,
+) -> Result<&'static str, String> {
+    let key = match key.replace('_', "").as_str() {
+        "tabwidth" => "tab_width",
+        "softtabs" => "soft_tabs",
+        "linenumbers" => "line_numbers",
+        "autopairs" => "auto_pairs",
+        _ => return Err(format!("Unknown setting: {}", key)),
+    };
+    let value = value
+        .parse::<i64>()
+        .map(Value::Integer)
+        .or_else(|_| value.parse::<bool>().map(Value::Boolean))
+        .unwrap_or_else(|_| Value::String(value.to_string()));
+    apply_file_setting(settings, key, &value)?;
+    Ok(key)
+}
+
+// Applies a single setting that can also be set per filetype.
+fn apply_file_setting(settings: &mut Settings, key: &str, value: &Value) -> Result<(), String> {
+    match key {
+        "tab_width" => settings.tab_width = whole_number(key, value, 1, 16)? as usize,
+        "soft_tabs" => settings.soft_tabs = boolean(key, value)?,
+        "line_numbers" => settings.line_numbers = boolean(key, value)?,
+        "auto_pairs" => settings.auto_pairs = boolean(key, value)?,
+        "theme" | "autosave_interval" | "status_timeout" | "dirty_quit_count" => {
+            return Err(format!("{} can't be set per filetype", key))
+        }
+        _ => return Err(format!("unknown setting \"{}\"", key)),
+    }
+    Ok(())
+}
+
+// Reads a setting that should be a whole number within a range.
+fn whole_number(key: &str, value: &Value, min: u64, max: u64) -> Result<u64, String> {
+    value
+        .as_integer()
+        .and_then(|n| u64::try_from(n).ok())
+        .filter(|n| (min..=max).contains(n))
+        .ok_or_else(|| format!("{} should be a whole number from {} to {}", key, min, max))
+}
+
+// Reads a setting that should be true or false.
+fn boolean(key: &str, value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("{} should be true or false", key))
+}
+
+// CONSTS //
+
+const CONFIG_FILE: &str = "config.toml";
@@ -3,24 +3,100 @@
 // Quit (Ctrl+Q) - Close the editor
 // Save (Ctrl+S) - Save the current file
 // Move (Arrow keys) - Move the cursor in the editor
+// MoveWord (Ctrl+Left/Right or Alt+Left/Right) - Move the cursor to the previous word start, or the next word end
+// NextWord - Move the cursor to the start of the next word, which may be on a following line. (Unbound by default; used by vim mode's w.)
 // Page (Home/End/PgUp/PgDn) - Snap cursor to the left/right/top/bottom of the editor
 // Write - Input a character into a line of text
+// Click (Left mouse button) - Place the cursor at a screen position (column, row -- both 1-indexed).
+// Drag (Left mouse button, held) - Select text from where the mouse was pressed down to a screen position.
+// Scroll (Mouse wheel) - Scroll the view up or down, without moving the cursor.
+// Paste - A block of text pasted into the terminal (with bracketed paste), to be inserted all at once
 // Delete (Backspace / Del / Ctrl+H) - Delete a character in the line of text. Delete left or right of the cursor.
+// DeleteChar - Like Delete, but never across a line break: at the start or end of a line, nothing is deleted. (Unbound by default; used by vim mode's x and X.)
+// DeleteWord (Alt+Backspace / Ctrl+W, Alt+D) - Delete up to the previous or next word boundary.
 // Cancel - Used for exiting any prompt interactions.
 // Find - Used to initialize a 'find' prompt interaction.
+// GoTo (Ctrl+G) - Used to initialize a 'go to line' prompt interaction.
+// FindNext / FindPrev (Ctrl+N / Ctrl+P) - Jump to the next or previous match of the last search.
+// Select (Shift+Arrow keys) - Move the cursor, selecting text from where it started.
+// SetMark (Ctrl+Space) - Toggle a selection mark at the cursor. While it's set, moving the cursor selects text.
+// Dedent (Shift+Tab) - Remove a level of indentation from the current line, or every selected line. (Tab indents a selection.)
+// Search (Ctrl+S / Ctrl+R in the emacs preset) - Start a search forward (true) or backward from the cursor. While a search prompt is open, step to the next or previous match.
+// Copy / Cut (Ctrl+C / Ctrl+X) - Copy or cut the selection (or the current line) into the kill ring.
+// CopyBack - Copy like Copy does, then move the cursor back to the start of what was copied. (Unbound by default; used by vim mode's y.)
+// KillLine (Ctrl+K in the emacs preset) - Cut from the cursor to the end of the line into the kill ring. Kills in a row build up one entry.
+// Yank (Ctrl+V) - Paste the newest entry in the kill ring.
+// YankAfter - Paste the newest entry in the kill ring after the cursor, or below the current line if it's whole lines. (Unbound by default; used by vim mode's p.)
+// YankCycle (Alt+Y) - Straight after a paste, swap the pasted text for the next oldest entry in the kill ring.
+// JumpBracket (Ctrl+B) - Move the cursor to the bracket that matches the one it's on.
+// ToggleComment (Ctrl+/ or Alt+;) - Comment out or uncomment the current line or selection.
+// Undo / Redo (Ctrl+Z / Ctrl+Y) - Undo the last change to the text, or redo the last change that was undone.
+// MacroRecord (F3) - Used to initialize a prompt for the register to record a macro into. While recording, stops recording instead.
+// MacroPlay (F4) - Used to initialize a prompt for the register to replay a macro from, and how many times.
+// DuplicateLines (Ctrl+L) - Duplicate the current line, or the selected lines.
+// MoveLines (Alt+Up / Alt+Down) - Move the current line, or the selected lines, up or down by one.
+// DeleteLines (Ctrl+K) - Delete the current line, or the selected lines.
+// JoinLines (Alt+J) - Join the next line onto the current one, or the selected lines into one.
+// BlockSelect (Alt+B) - Start or end a block selection, covering a rectangle of columns between where it started and the cursor.
+// AddCursor (Ctrl+Up / Ctrl+Down) - Add a cursor on the line above or below. The main cursor moves there, and an extra cursor is left behind.
+// AddCursorNext (Ctrl+D) - Add a cursor at the next occurrence of the word under the cursor.
+// AddCursorMatches (Alt+L) - Put a cursor at every match of the last search.
+// Filter (Alt+|) - Used to initialize a prompt for a shell command to run the selected lines (or the whole file) through.
+// ShellInsert (Alt+!) - Used to initialize a prompt for a shell command, whose output is inserted at the cursor.
+// CommandLine (Ctrl+E) - Used to initialize a command line prompt interaction, for running named commands.
+#[derive(Clone, PartialEq, Debug)]
 pub enum InputEvent {
     Quit,
     Move(Direction),
+    MoveWord(Direction),
+    NextWord,
     Page(Direction),
     Write(char),
+    Paste(String),
+    Click(u16, u16),
+    Drag(u16, u16),
+    Scroll(Direction),
     Delete(Direction),
+    DeleteChar(Direction),
+    DeleteWord(Direction),
     Save,
     Cancel,
     Find,
+    GoTo,
+    FindNext,
+    FindPrev,
+    Search(bool),
+    Select(Direction),
+    SetMark,
+    Dedent,
+    Copy,
+    CopyBack,
+    Cut,
+    KillLine,
+    Yank,
+    YankAfter,
+    YankCycle,
+    JumpBracket,
+    ToggleComment,
+    Undo,
+    Redo,
+    MacroRecord,
+    MacroPlay,
+    DuplicateLines,
+    MoveLines(Direction),
+    DeleteLines,
+    JoinLines,
+    BlockSelect,
+    AddCursor(Direction),
+    Filter,
+    ShellInsert,
+    AddCursorNext,
+    AddCursorMatches,
+    CommandLine,
 }
 
 // Directions. Used to classify InputEvents.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Direction {
     Up,
     Down,
@@ -31,19 +107,75 @@ pub enum Direction {
 // Status Content. Used to classify the Content of a StatusMessage.
 // Help - Keybind help message shown on launch
 // SaveSuccess - Shown on file write success
+// SaveFailed - Shown when the file can't be written. String for the error.
 // DirtyWarning - Shown when closing a modified, unsaved file. i16 for # of force quit inputs.
 // SaveAs - Shown when closing an unnamed file. String for user inputted file name.
 // SaveAbort - Shown when SaveAs is aborted.
 // Find - Shown when CTRL+F is used to search the opened file. String for user inputted query.
+// FindResult - Shown while a search prompt is open. Contains the query, the current match # (1-indexed), the total # of matches, and whether the search wrapped around.
+// FindRepeat - Shown when stepping through search results outside of the prompt. Contains the current match #, total # of matches, and whether the search wrapped around.
+// FindMiss - Shown when a search has no matches. String for the query (empty if there was no previous search).
+// GoTo - Shown when CTRL+G is used to jump to a line. String for the user inputted target.
+// GoToLine - Shown after jumping to a line. Contains the line # (1-indexed), and the total # of lines.
+// GoToInvalid - Shown when a go-to-line target can't be understood. String for the target.
+// ClipboardEmpty - Shown when trying to paste with nothing in the kill ring.
+// ClipboardError - Shown when the system clipboard helper fails. String for the error.
+// NoCommentSyntax - Shown when trying to toggle a comment in a file whose language has no comments.
+// Command - Shown while the command line is open. Contains the user inputted command, and the command names that it could complete to.
+// CommandError - Shown when a command can't be run. String for the reason.
+// OptionSet - Shown after a setting is changed from the command line. Contains the setting's name and its new value.
+// Replaced - Shown after a replace command. Contains the # of replacements made.
+// Filter - Shown while typing a shell command to filter through. String for the user inputted command.
+// ShellInsert - Shown while typing a shell command whose output is to be inserted. String for the user inputted command.
+// ShellRunning - Shown while a shell command's output is waiting to be inserted. String for the command.
+// ShellInserted - Shown after a shell command's output is inserted. Contains the # of lines inserted, and whether the output was cut off.
+// ShellCancelled - Shown when a running shell command is stopped with ESC. String for the command.
+// LinesChanged - Shown after lines are sorted, made unique or reversed. Contains the # of lines that changed.
+// UndoEmpty - Shown when there's nothing to undo (false) or redo (true).
+// MacroRecord - Shown while picking a register to record a macro into. String for the user inputted register.
+// MacroPlay - Shown while picking a macro to replay. String for the user inputted register and count.
+// MacroRecording - Shown when a macro starts recording. String for the register.
+// MacroSaved - Shown when a macro stops recording. Contains the register, and the # of events recorded.
+// MacroMissing - Shown when trying to replay an empty register. String for the register.
+// MacroStopped - Shown when a replay stops early. String for the reason.
+// ConfigError - Shown on launch when a config file has problems. Contains every problem found.
 // PromptAbort - Shown when a prompt interaction is aborted, and we don't want to display any message.
 #[derive(PartialEq)]
 pub enum StatusContent {
     Help,
     SaveSuccess,
+    SaveFailed(String),
     DirtyWarning(i16),
     SaveAs(String),
     SaveAbort,
     Find(String),
+    FindResult(String, usize, usize, bool),
+    FindRepeat(usize, usize, bool),
+    FindMiss(String),
+    GoTo(String),
+    GoToLine(usize, usize),
+    GoToInvalid(String),
+    ClipboardEmpty,
+    ClipboardError(String),
+    NoCommentSyntax,
+    Command(String, Vec<String>),
+    CommandError(String),
+    OptionSet(String, String),
+    Replaced(usize),
+    LinesChanged(usize),
+    Filter(String),
+    ShellInsert(String),
+    ShellRunning(String),
+    ShellInserted(usize, bool),
+    ShellCancelled(String),
+    UndoEmpty(bool),
+    MacroRecord(String),
+    MacroPlay(String),
+    MacroRecording(String),
+    MacroSaved(String, usize),
+    MacroMissing(String),
+    MacroStopped(String),
+    ConfigError(Vec<String>),
     PromptAbort,
 }
 
@@ -58,7 +190,20 @@ pub enum WriteMode {
 // Prompt Result. Contains some kind of data for the OH to pass to the controller.
 // FileRename - Sent after a successful file rename. Contains the new file name.
 // TextSearch - Incremental. Sent on each query input. Contains the query.
+// FindConfirm - Sent when a search is confirmed. The cursor stays on the current match.
+// GoTo - Sent when a go-to-line prompt is confirmed. Contains the target, as it was typed.
+// Command - Sent when the command line is confirmed. Contains the command, as it was typed.
+// MacroRecord / MacroPlay - Sent when a macro prompt is confirmed. Contains the input, as it was typed.
+// Filter - Sent when a filter prompt is confirmed. Contains the shell command.
+// ShellInsert - Sent when a shell insert prompt is confirmed. Contains the shell command.
 pub enum PromptResult {
     FileRename(String),
     TextSearch(String),
+    FindConfirm,
+    GoTo(String),
+    Command(String),
+    MacroRecord(String),
+    MacroPlay(String),
+    Filter(String),
+    ShellInsert(String),
 }
@@ -0,0 +1,142 @@
+use std::path::Path;
+
+// Language. Describes the parts of a programming language that the editor needs to know about when editing a file.
+// extensions: file extensions (without the dot) that mark a file as being written in this language.
+// indent_after: if a line ends with one of these characters, the line after it is indented one level deeper.
+// dedent_on: typing one of these characters at the start of a line (after only whitespace) takes the line back out one level.
+// quotes: characters that open and close a string. Strings end at the end of a line, and a backslash escapes the next character.
+// line_comment: the marker that starts a comment running to the end of the line, if the language has one.
+// block_comment: the markers that open and close a comment that can span several lines, if the language has them.
+// auto_pairs: openers that get their closer inserted automatically when typed, as (opener, closer) pairs.
+// keywords, types: words that are highlighted as keywords or types. Languages with no keywords aren't syntax highlighted.
+pub struct Language {
+    pub extensions: &'static [&'static str],
+    pub indent_after: &'static [char],
+    pub dedent_on: &'static [char],
+    pub quotes: &'static [char],
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    pub auto_pairs: &'static [(char, char)],
+    pub keywords: &'static [&'static str],
+    pub types: &'static [&'static str],
+}
+
+impl Language {
+    // Picks a language for a file, based on its extension. Falls back to plain text if the extension isn't known.
+    pub fn detect(file_name: &str) -> &'static Language {
+        let ext = Path::new(file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+        LANGUAGES
+            .iter()
+            .find(|lang| lang.extensions.contains(&ext))
+            .unwrap_or(&PLAIN_TEXT)
+    }
+
+    // Whether a line ending with the given text should indent the line after it.
+    // Trailing whitespace is ignored, so "if (x) { " still counts as ending with a brace.
+    pub fn indents_after(&self, text: &str) -> bool {
+        text.trim_end()
+            .chars()
+            .last()
+            .is_some_and(|c| self.indent_after.contains(&c))
+    }
+}
+
+// CONSTS //
+
+const C_STYLE_INDENT: &[char] = &['{', '(', '['];
+const C_STYLE_DEDENT: &[char] = &['}', ')', ']'];
+const C_STYLE_PAIRS: &[(char, char)] =
+    &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+// Const lists for syntax highlighting.
+const C_KEYWORDS: &[&str] = &[
+    "switch", "if", "while", "for", "break", "continue", "return", "else", "struct", "union",
+    "typedef", "static", "enum", "class", "case",
+];
+const C_TYPES: &[&str] = &[
+    "int", "long", "double", "float", "char", "unsigned", "signed", "void", "#include",
+];
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "fn", "for", "if", "impl", "in",
+    "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "static",
+    "struct", "trait", "type", "unsafe", "use", "where", "while",
+];
+const RUST_TYPES: &[&str] = &[
+    "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "isize", "str", "u8", "u16", "u32",
+    "u64", "usize", "String", "Self",
+];
+const PYTHON_KEYWORDS: &[&str] = &[
+    "and", "as", "break", "class", "continue", "def", "elif", "else", "except", "finally", "for",
+    "from", "if", "import", "in", "is", "lambda", "not", "or", "pass", "raise", "return", "try",
+    "while", "with", "yield",
+];
+const PYTHON_TYPES: &[&str] = &[
+    "int", "float", "str", "bool", "list", "dict", "set", "tuple",
+];
+const SHELL_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+    "in", "function", "return", "local", "export",
+];
+
+const PLAIN_TEXT: Language = Language {
+    extensions: &[],
+    indent_after: &[],
+    dedent_on: &[],
+    quotes: &[],
+    line_comment: None,
+    block_comment: None,
+    auto_pairs: &[],
+    keywords: &[],
+    types: &[],
+};
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        extensions: &["c", "h", "cpp", "hpp", "cc"],
+        indent_after: C_STYLE_INDENT,
+        dedent_on: C_STYLE_DEDENT,
+        quotes: &['"', '\''],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        auto_pairs: C_STYLE_PAIRS,
+        keywords: C_KEYWORDS,
+        types: C_TYPES,
+    },
+    Language {
+        extensions: &["rs"],
+        indent_after: C_STYLE_INDENT,
+        dedent_on: C_STYLE_DEDENT,
+        // no single quotes, since they also mark lifetimes
+        quotes: &['"'],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        auto_pairs: &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')],
+        keywords: RUST_KEYWORDS,
+        types: RUST_TYPES,
+    },
+    Language {
+        extensions: &["py"],
+        indent_after: &[':', '(', '[', '{'],
+        dedent_on: C_STYLE_DEDENT,
+        quotes: &['"', '\''],
+        line_comment: Some("#"),
+        block_comment: None,
+        auto_pairs: C_STYLE_PAIRS,
+        keywords: PYTHON_KEYWORDS,
+        types: PYTHON_TYPES,
+    },
+    Language {
+        extensions: &["sh", "bash", "zsh"],
+        indent_after: &['{', '('],
+        dedent_on: &['}', ')'],
+        quotes: &['"', '\''],
+        line_comment: Some("#"),
+        block_comment: None,
+        auto_pairs: C_STYLE_PAIRS,
+        keywords: SHELL_KEYWORDS,
+        types: &[],
+    },
+];
@@ -1,3 +1,4 @@
 pub mod enums;
+pub mod language;
 pub mod payload;
 pub mod textrow;
@@ -1,14 +1,19 @@
+use crate::data::textrow::TextRow;
 use std::fmt;
 use std::time::Instant;
 
 // Cursor State. Represents the state of the CursorHandler at a moment in time.
 // Contains essential CursorHandler fields, for use by the renderer.
+// anchor is the (column, row) position where the current selection started, if there is one.
+// block is whether that selection is a block (a rectangle of columns), rather than a run of text.
 #[derive(Copy, Clone)]
 pub struct CursorState {
     pub cx: i16,
     pub cy: i16,
     pub row_offset: i16,
     pub col_offset: i16,
+    pub anchor: Option<(usize, usize)>,
+    pub block: bool,
 }
 
 impl CursorState {
@@ -19,6 +24,8 @@ impl CursorState {
             cy: 0,
             row_offset: 0,
             col_offset: 0,
+            anchor: None,
+            block: false,
         }
     }
 
@@ -28,6 +35,34 @@ impl CursorState {
         (self.cy + 1).to_string()
     }
 
+    // Returns the position of this cursor state within the document, as (column, row).
+    // cx and cy are screen coordinates, so the offsets need to be added back on.
+    pub fn position(self) -> (usize, usize) {
+        (
+            (self.cx + self.col_offset) as usize,
+            (self.cy + self.row_offset) as usize,
+        )
+    }
+
+    // Returns the range of text between the anchor and the cursor, if a selection has been started.
+    // Block selections aren't a range of text, so they're left out.
+    pub fn selection(self) -> Option<Selection> {
+        match self.block {
+            true => None,
+            false => self.anchor.map(|a| Selection::new(a, self.position())),
+        }
+    }
+
+    // Returns the block between the anchor and the cursor, if a block selection has been started.
+    pub fn block(self, data: &[TextRow], tab_width: usize) -> Option<Block> {
+        match self.block {
+            true => self
+                .anchor
+                .map(|a| Block::new(a, self.position(), data, tab_width)),
+            false => None,
+        }
+    }
+
     // Update the values of this CursorState and return the updated CursorState.
     pub fn update(
         &mut self,
@@ -44,24 +79,94 @@ impl CursorState {
     }
 }
 
+// Block. A rectangle of text, for column selections. Stretches between an anchor and the cursor.
+// top and bottom are the first and last rows of the block. left and right are display columns, rather than grapheme
+// indices, so that the block lines up on screen. The column at right is not part of the block.
+#[derive(Copy, Clone)]
+pub struct Block {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Block {
+    // Blocks are constructed from two corners, as (column, row) positions in any order. Each corner's column is
+    // converted into a display column using its own row.
+    pub fn new(a: (usize, usize), b: (usize, usize), data: &[TextRow], tab_width: usize) -> Self {
+        let col =
+            |(x, y): (usize, usize)| data.get(y).map_or(x, |row| row.display_col(x, tab_width));
+        let (ca, cb) = (col(a), col(b));
+        Self {
+            top: a.1.min(b.1),
+            bottom: a.1.max(b.1),
+            left: ca.min(cb),
+            right: ca.max(cb),
+        }
+    }
+
+    // Whether the block is a single column wide, with no text in it.
+    pub fn is_empty(self) -> bool {
+        self.left == self.right
+    }
+}
+
+// Selection. Represents a range of selected text, stretching between an anchor and the cursor.
+// start and end are (column, row) positions within the document, ordered so that start always comes first.
+// The grapheme at end is not part of the selection.
+#[derive(Copy, Clone)]
+pub struct Selection {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+impl Selection {
+    // Selections can be constructed from two positions in any order.
+    pub fn new(a: (usize, usize), b: (usize, usize)) -> Self {
+        if (a.1, a.0) <= (b.1, b.0) {
+            Self { start: a, end: b }
+        } else {
+            Self { start: b, end: a }
+        }
+    }
+
+    // Whether or not the selection covers any text at all.
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
+    // Returns the first and last rows touched by the selection, for line-wise operations.
+    // A selection ending at the very start of a row doesn't count as touching that row.
+    pub fn rows(self) -> (usize, usize) {
+        if self.end.0 == 0 && self.end.1 > self.start.1 {
+            (self.start.1, self.end.1 - 1)
+        } else {
+            (self.start.1, self.end.1)
+        }
+    }
+}
+
 // Status Message. Represents the most recent status message displayed by the renderer.
 // Contains the text of the message, and a timestamp representing when the message was fired.
 // Choosing to use an Instant here instead of SystemTime, as all we really need is a way to compare to Instant::now() on render.
 // An immortal StatusMessage is basically a Prompt -- its sent time is irrelevant to its render.
+// timeout is how many seconds a mortal message stays up for.
 pub struct StatusMessage {
     pub content: String,
     pub last_sent: Option<Instant>,
     pub immortal: bool,
+    timeout: u64,
 }
 
 impl StatusMessage {
     // Set with an empty string to start with. last_sent is set to right now.
-    // Must specify whether this is a mortal or immortal status.
-    pub fn new(immortal: bool) -> Self {
+    // Must specify whether this is a mortal or immortal status, and how long a mortal status lasts.
+    pub fn new(immortal: bool, timeout: u64) -> Self {
         Self {
             content: "".to_string(),
             last_sent: None,
             immortal,
+            timeout,
         }
     }
 
@@ -74,7 +179,7 @@ impl StatusMessage {
     }
 
     // Returns whether or not the renderer should print this status message.
-    // We print a status message if it's been live for less than 5 seconds.
+    // We print a status message if it's been live for less than its timeout.
     // If this is an immortal status message, we always print it.
     pub fn should_print(&mut self) -> bool {
         if self.immortal {
@@ -82,7 +187,7 @@ impl StatusMessage {
         }
         match self.last_sent {
             None => false,
-            Some(t) => t.elapsed().as_secs() <= 5,
+            Some(t) => t.elapsed().as_secs() <= self.timeout,
         }
     }
 
@@ -134,6 +239,8 @@ impl DirtyStatus {
     }
 }
 
+// Search Item. Represents a single search match within the document.
+// cx is the grapheme index where the match begins, cy is the row it was found on.
 #[derive(Copy, Clone)]
 pub struct SearchItem {
     pub cx: i16,
@@ -147,4 +254,9 @@ impl SearchItem {
             cy: cy as i16,
         }
     }
+
+    // Returns the position of this match within the document, as (column, row).
+    pub fn position(self) -> (usize, usize) {
+        (self.cx as usize, self.cy as usize)
+    }
 }
@@ -1,5 +1,6 @@
 use std::fmt;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 // TextRow. Represents a line of text in the editor.
 // raw_text: The complete text of the line.
@@ -20,21 +21,24 @@ impl TextRow {
         }
     }
 
-    // Truncate a TextRow's text to a given length.
-    pub fn truncate(&mut self, l: u16) -> &mut TextRow {
-        self.text.truncate(l.into());
-        self
-    }
-
-    // Take a substring of a TextRow's text, starting at a given index.
-    pub fn substring(&mut self, start: i16) -> &mut TextRow {
-        let len = self.raw_text.len();
-        self.text = self
-            .raw_text
-            .chars()
-            .skip(start.try_into().unwrap())
-            .take(len)
-            .collect();
+    // Sets a TextRow's text to the part of the row that fits on screen: the graphemes from a given index onwards, until they
+    // fill a given number of columns. Tabs are expanded to spaces out to the next tab stop, so the terminal never places them.
+    pub fn clip(&mut self, start: usize, width: usize, tab_width: usize) -> &mut TextRow {
+        let mut col = self.display_col(start, tab_width);
+        let end = col + width;
+        self.text = String::new();
+        for g in self.raw_text.graphemes(true).skip(start) {
+            let w = cell_width(g, col, tab_width);
+            if col + w > end {
+                break;
+            }
+            if g == "\t" {
+                self.text.push_str(&" ".repeat(w));
+            } else {
+                self.text.push_str(g);
+            }
+            col += w;
+        }
         self
     }
 
@@ -44,6 +48,33 @@ impl TextRow {
         (self.raw_text.graphemes(true).count() as i16) + 1
     }
 
+    // Converts a grapheme index within the row into a display column -- how many terminal cells the graphemes before it
+    // take up. Wide characters take up two, and tabs reach to the next tab stop. Indices past the end of the row count for
+    // one column each, as if it were padded out with spaces.
+    pub fn display_col(&self, grapheme_idx: usize, tab_width: usize) -> usize {
+        let mut count = 0;
+        let mut col = 0;
+        for g in self.raw_text.graphemes(true).take(grapheme_idx) {
+            col += cell_width(g, col, tab_width);
+            count += 1;
+        }
+        col + grapheme_idx - count
+    }
+
+    // Converts a display column into the index of the first grapheme that starts at (or after) it. The opposite of display_col.
+    pub fn grapheme_at_col(&self, col: usize, tab_width: usize) -> usize {
+        let mut at = 0;
+        let mut count = 0;
+        for g in self.raw_text.graphemes(true) {
+            if at >= col {
+                return count;
+            }
+            at += cell_width(g, at, tab_width);
+            count += 1;
+        }
+        count + col.saturating_sub(at)
+    }
+
     // Updates the text of a TextRow.
     pub fn update_text(&mut self, text: String) {
         self.raw_text.clone_from(&text);
@@ -51,16 +82,28 @@ impl TextRow {
     }
 
     // Returns a list of all indices in the row where a given substr begins.
-    // I have a feeling there's going to be something wrong with this method, since it's not using a grapheme representation.
-    pub fn find_idx_at_substr(&self, substr: &String) -> Vec<(usize, &str)> {
-        let substr_len = substr.len();
+    // match_indices hands back byte offsets, so each one is converted to a grapheme index before it's returned.
+    pub fn find_idx_at_substr(&self, substr: &str) -> Vec<(usize, &str)> {
+        if substr.is_empty() {
+            return Vec::new();
+        }
         self.raw_text
             .match_indices(substr)
-            .map(|(idx, match_str)| (idx + substr_len, match_str))
+            .map(|(idx, match_str)| (self.raw_text[..idx].graphemes(true).count(), match_str))
             .collect::<Vec<(_, _)>>()
     }
 }
 
+// Returns how many terminal cells a grapheme takes up when it's drawn at a given column. A tab reaches to the next multiple
+// of tab_width, and anything with no width of its own (like a control character) still gets a cell.
+pub fn cell_width(g: &str, col: usize, tab_width: usize) -> usize {
+    if g == "\t" {
+        tab_width - col % tab_width
+    } else {
+        g.width().max(1)
+    }
+}
+
 impl fmt::Display for TextRow {
     // We display a TextRow by printing out its text.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -1,25 +1,54 @@
 use crate::{
-    backend::{cursor::CursorHandler, operations::OperationsHandler},
+    backend::{
+        commands::{self, Command},
+        cursor::CursorHandler,
+        lines,
+        macros::{self, MacroRecorder},
+        operations::OperationsHandler,
+        shell::{self, ShellJob},
+    },
+    config::Config,
     data::{
-        enums::{Direction, InputEvent, PromptResult, WriteMode},
-        payload::SearchItem,
+        enums::{Direction, InputEvent, PromptResult, StatusContent, WriteMode},
+        payload::{Block, SearchItem, Selection},
         textrow::TextRow,
     },
     gfx::render::RenderDriver,
     utils,
 };
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, Error},
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufRead, BufReader, Error, ErrorKind},
+    time::{Duration, Instant},
 };
 
 // RenderController. Parses user input and calls the appropriate processing / rendering methods within the crate.
 // Contains a CursorHandler and OperationsHandler on initialization.
+// autosave_interval is how long a modified file can go without being saved, before it's saved automatically (None if autosave
+// is off). last_save is when the file was last saved, or last known to have no unsaved changes.
+// queued_events holds events for the editor to handle next: one queued up by a command run from the command line, or
+// every event of a macro being replayed. replaying is whether a replay is running, and replayed counts its events.
+// macros records events into registers, for replaying.
+// search_backward is whether the open search prompt was started as a backward search.
+// shell_job is a shell command running in the background, whose output gets inserted once it's done. shell_insert_at is
+// where it goes: wherever the cursor was when the command was started.
+// quit_after_save is set when :wq opens the Save As prompt, so that the editor still quits once the file has a name.
 pub struct RenderController {
     cursor: CursorHandler,
     operations: OperationsHandler,
     file_name: String,
     mode: WriteMode,
+    autosave_interval: Option<Duration>,
+    last_save: Instant,
+    queued_events: VecDeque<InputEvent>,
+    search_backward: bool,
+    macros: MacroRecorder,
+    replaying: bool,
+    replayed: usize,
+    shell_job: Option<ShellJob>,
+    shell_insert_at: (usize, usize),
+    quit_after_save: bool,
 }
 
 impl RenderController {
@@ -27,23 +56,340 @@ impl RenderController {
     // Uses this RenderDriver to construct an OperationsHandler.
     // Write mode is set to Editor initially.
     // Note: No support whatsoever for mid-session window resizing (yet).
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
         let window_size = utils::get_window_size();
         let mut cursor = CursorHandler::new(window_size.rows, window_size.cols);
         let state = cursor.get_state();
-        let render = RenderDriver::new(state);
+        let autosave_interval =
+            Some(Duration::from_secs(config.autosave_interval)).filter(|d| !d.is_zero());
+        let render = RenderDriver::new(state, config);
         Self {
             cursor,
             operations: OperationsHandler::new(render),
             file_name: "".to_string(),
             mode: WriteMode::Editor,
+            autosave_interval,
+            last_save: Instant::now(),
+            queued_events: VecDeque::new(),
+            search_backward: false,
+            macros: MacroRecorder::new(),
+            replaying: false,
+            replayed: 0,
+            shell_job: None,
+            shell_insert_at: (0, 0),
+            quit_after_save: false,
+        }
+    }
+
+    // From a Move InputEvent's Direction, move the cursor. Plain movement drops a selection started with Shift.
+    pub fn queue_move(&mut self, d: Direction) {
+        if matches!(self.mode, WriteMode::Editor) {
+            self.cursor.release_shift_anchor();
+        }
+        self.move_cursor(d);
+    }
+
+    // From a Select InputEvent's Direction, drop an anchor at the cursor (if a selection isn't already in progress), then move the cursor.
+    // Text can only be selected in the editor.
+    pub fn queue_select(&mut self, d: Direction) {
+        if matches!(self.mode, WriteMode::Editor) {
+            self.cursor.set_anchor(true);
+            self.move_cursor(d);
+        }
+    }
+
+    // Toggles a selection mark at the cursor. Unlike a Shift selection, the mark stays set as the cursor moves around.
+    pub fn queue_mark(&mut self) {
+        if matches!(self.mode, WriteMode::Editor) {
+            if self.cursor.has_anchor() {
+                self.cursor.clear_anchor();
+            } else {
+                self.cursor.set_anchor(false);
+            }
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Starts a block selection at the cursor, or ends the one in progress. Moving the cursor stretches the block out to it.
+    pub fn queue_block_select(&mut self) {
+        if matches!(self.mode, WriteMode::Editor) {
+            if self.cursor.get_state().block {
+                self.cursor.clear_anchor();
+            } else {
+                self.cursor.set_block_anchor();
+            }
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Returns the position of a block's top left corner.
+    fn block_start(&mut self, block: Block) -> (usize, usize) {
+        let tab_width = self.operations.settings().tab_width;
+        let x = self
+            .operations
+            .get_text()
+            .get(block.top)
+            .map_or(0, |row| row.grapheme_at_col(block.left, tab_width));
+        (x, block.top)
+    }
+
+    // Indents (or dedents) the current line, or every line touched by the selection.
+    // The cursor and anchor are shifted along with the text underneath them.
+    pub fn queue_indent(&mut self, dedent: bool) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        let state = self.cursor.get_state();
+        let (x, y) = state.position();
+        let (first, last) = match state.selection() {
+            Some(sel) => sel.rows(),
+            None => (y, y),
+        };
+
+        let shifts = self.operations.indent_lines(first, last, dedent);
+        let shift = |(col, row): (usize, usize)| match shifts.get(row.wrapping_sub(first)) {
+            Some(s) => ((col as isize + s).max(0) as usize, row),
+            None => (col, row),
+        };
+        if let Some(a) = state.anchor {
+            self.cursor.move_anchor(shift(a));
+        }
+        let (new_x, new_y) = shift((x, y));
+        self.cursor
+            .teleport(new_x, new_y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Returns the rows that a line operation works on: every row touched by the selection, or just the cursor's row.
+    fn line_range(&mut self) -> (usize, usize) {
+        let state = self.cursor.get_state();
+        match state.selection() {
+            Some(sel) => sel.rows(),
+            None => (state.position().1, state.position().1),
+        }
+    }
+
+    // Shifts the cursor (and the anchor of any selection) down by a number of rows, or up if it's negative.
+    fn shift_rows(&mut self, delta: isize) {
+        let state = self.cursor.get_state();
+        let shift = |(x, y): (usize, usize)| (x, (y as isize + delta).max(0) as usize);
+        if let Some(a) = state.anchor {
+            self.cursor.move_anchor(shift(a));
+        }
+        let (x, y) = shift(state.position());
+        self.cursor.teleport(x, y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Duplicates the current line, or every line touched by the selection. The cursor (and selection) move onto the copy.
+    pub fn queue_duplicate_lines(&mut self) {
+        if matches!(self.mode, WriteMode::Editor) {
+            let (first, last) = self.line_range();
+            let count = self.operations.duplicate_lines(first, last);
+            self.shift_rows(count as isize);
+        }
+    }
+
+    // Moves the current line, or every line touched by the selection, up or down past the line next to it.
+    // The cursor (and selection) move along with the text.
+    pub fn queue_move_lines(&mut self, d: Direction) {
+        if matches!(self.mode, WriteMode::Editor) {
+            let (first, last) = self.line_range();
+            let up = matches!(d, Direction::Up);
+            if self.operations.move_lines(first, last, up) {
+                self.shift_rows(if up { -1 } else { 1 });
+            }
+        }
+    }
+
+    // Deletes the current line, or every line touched by the selection. The cursor stays in the same column, on the
+    // line that took their place.
+    pub fn queue_delete_lines(&mut self) {
+        if matches!(self.mode, WriteMode::Editor) {
+            let (first, last) = self.line_range();
+            let x = self.cursor.get_state().position().0;
+            self.operations.delete_lines(first, last);
+            self.cursor.clear_anchor();
+            self.cursor.teleport(x, first, self.operations.get_text());
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Joins the next line onto the current one, or every line touched by the selection into one.
+    // The cursor goes to the last join.
+    pub fn queue_join_lines(&mut self) {
+        if matches!(self.mode, WriteMode::Editor) {
+            let (first, last) = self.line_range();
+            if let Some(x) = self.operations.join_lines(first, last) {
+                self.cursor.clear_anchor();
+                self.cursor.teleport(x, first, self.operations.get_text());
+                self.operations.update_cursor_state(self.cursor.get_state());
+            }
         }
     }
 
-    // From a Move InputEvent's Direction, tell the CursorHandler to handle cursor movement.
+    // Comments out (or uncomments) the current line, or every line touched by the selection.
+    // A selection within part of a single line is wrapped in a block comment instead, if the language has them.
+    // The cursor and anchor are shifted along with the text underneath them.
+    pub fn queue_comment(&mut self) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        let state = self.cursor.get_state();
+        let (x, y) = state.position();
+        if let Some(sel) = state.selection() {
+            if let Some((end_x, end_y)) = self.operations.toggle_block_comment(sel) {
+                self.cursor.move_anchor(sel.start);
+                self.cursor
+                    .teleport(end_x, end_y, self.operations.get_text());
+                self.operations.update_cursor_state(self.cursor.get_state());
+                return;
+            }
+        }
+        let (first, last) = match state.selection() {
+            Some(sel) => sel.rows(),
+            None => (y, y),
+        };
+
+        let shifts = match self.operations.toggle_line_comments(first, last) {
+            Some(shifts) => shifts,
+            None => return,
+        };
+        let shift = |(col, row): (usize, usize)| match shifts.get(row.wrapping_sub(first)) {
+            Some((at, s)) if col >= *at => ((col as isize + s).max(*at as isize) as usize, row),
+            _ => (col, row),
+        };
+        if let Some(a) = state.anchor {
+            self.cursor.move_anchor(shift(a));
+        }
+        let (new_x, new_y) = shift((x, y));
+        self.cursor
+            .teleport(new_x, new_y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Copies the selection (or the current line) into the kill ring. The selection is cleared afterwards.
+    // back moves the cursor to the start of the selection, the way vim's y leaves it.
+    pub fn queue_copy(&mut self, back: bool) {
+        if matches!(self.mode, WriteMode::Editor) {
+            let state = self.cursor.get_state();
+            self.operations.copy_text(state);
+            self.cursor.clear_anchor();
+            let start = match (self.operations.block(state), state.selection()) {
+                (Some(block), _) => Some(self.block_start(block)),
+                (_, Some(sel)) => Some(sel.start),
+                _ => None,
+            };
+            if let (true, Some((x, y))) = (back, start) {
+                self.cursor.teleport(x, y, self.operations.get_text());
+            }
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Cuts the selection (or the current line) into the kill ring, leaving the cursor where the cut text used to start.
+    pub fn queue_cut(&mut self) {
+        if matches!(self.mode, WriteMode::Editor) {
+            let state = self.cursor.get_state();
+            let (x, y) = match (self.operations.block(state), state.selection()) {
+                (Some(block), _) => self.block_start(block),
+                (_, Some(sel)) if !sel.is_empty() => sel.start,
+                _ => (0, state.position().1),
+            };
+            self.operations.cut_text(state);
+            self.cursor.clear_anchor();
+            self.cursor.teleport(x, y, self.operations.get_text());
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Kills from the cursor to the end of the line (or the line break, at the end of a line) into the kill ring.
+    // The cursor stays put, so pressing this again keeps adding to the same kill.
+    pub fn queue_kill_line(&mut self) {
+        if matches!(self.mode, WriteMode::Editor) {
+            self.cursor.clear_anchor();
+            self.operations
+                .kill_line(self.cursor.get_state().position());
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Pastes from the kill ring, replacing any selected text. The whole paste is inserted in one go.
+    pub fn queue_paste(&mut self, after: bool) {
+        if matches!(self.mode, WriteMode::Editor) {
+            self.take_selection();
+            if let Some((x, y)) = self.operations.paste_text(self.cursor.get_state(), after) {
+                self.cursor.teleport(x, y, self.operations.get_text());
+                self.operations.update_cursor_state(self.cursor.get_state());
+            }
+        }
+    }
+
+    // Inserts a block of text pasted into the terminal, replacing any selected text. The whole block goes in as a single operation.
+    // The prompt is a single line, so only the first line of a paste is written to it. Control characters are left out,
+    // since written to the prompt one by one they'd act like keys (a tab completes, for one).
+    pub fn queue_paste_text(&mut self, text: &str) {
+        match self.mode {
+            WriteMode::Editor => {
+                self.take_selection();
+                let (x, y) = self
+                    .operations
+                    .insert_text(self.cursor.get_state().position(), text);
+                self.cursor.teleport(x, y, self.operations.get_text());
+                self.operations.update_cursor_state(self.cursor.get_state());
+            }
+            WriteMode::Prompt => {
+                let line = text.lines().next().unwrap_or("");
+                for c in line.chars().filter(|c| !c.is_control()) {
+                    self.queue_write(c);
+                }
+            }
+        }
+    }
+
+    // Swaps the text that was just pasted for the next oldest entry in the kill ring.
+    pub fn queue_paste_cycle(&mut self) {
+        if matches!(self.mode, WriteMode::Editor) {
+            if let Some((x, y)) = self.operations.cycle_paste(self.cursor.get_state()) {
+                self.cursor.teleport(x, y, self.operations.get_text());
+                self.operations.update_cursor_state(self.cursor.get_state());
+            }
+        }
+    }
+
+    // If there's any text selected, delete it and move the cursor to where the selection started.
+    // Either way, the selection is over afterwards. Returns whether any text was deleted.
+    // A block selection has its text deleted from every row, and the cursor goes to its top left corner.
+    fn take_selection(&mut self) -> bool {
+        if let Some(block) = self.operations.block(self.cursor.get_state()) {
+            self.cursor.clear_anchor();
+            self.operations.delete_block(block);
+            let (x, y) = self.block_start(block);
+            self.cursor.teleport(x, y, self.operations.get_text());
+            self.operations.update_cursor_state(self.cursor.get_state());
+            return !block.is_empty();
+        }
+        let sel = match self.cursor.get_state().selection() {
+            Some(sel) => sel,
+            None => return false,
+        };
+        self.cursor.clear_anchor();
+        if sel.is_empty() {
+            self.operations.update_cursor_state(self.cursor.get_state());
+            return false;
+        }
+
+        self.operations.delete_selection(sel);
+        let (x, y) = sel.start;
+        self.cursor.teleport(x, y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+        true
+    }
+
+    // Tell the CursorHandler to handle cursor movement in a given Direction.
     // Then give the updated CursorState to the RenderDriver.
     // It's also possible to move left and right in the prompt field.
-    pub fn queue_move(&mut self, d: Direction) {
+    fn move_cursor(&mut self, d: Direction) {
         let data = self.operations.get_text();
         let mode = &self.mode;
         match (d, mode) {
@@ -63,24 +409,120 @@ impl RenderController {
             (Direction::Right, WriteMode::Prompt) => self.operations.process_prompt_cursor(1),
             (Direction::Up, WriteMode::Prompt) => {
                 // Check last search result, if it exists
-                let idx = self.operations.update_prompt_match_idx(-1);
-                let i = self.operations.prompt_matches[idx];
-                self.update_prompt_match_cursor(&i);
+                if let Some(i) = self.operations.update_prompt_match_idx(-1) {
+                    self.update_prompt_match_cursor(&i);
+                }
             }
             (Direction::Down, WriteMode::Prompt) => {
                 // Check next search result, if it exists
-                let idx = self.operations.update_prompt_match_idx(1);
-                let i = self.operations.prompt_matches[idx];
-                self.update_prompt_match_cursor(&i);
+                if let Some(i) = self.operations.update_prompt_match_idx(1) {
+                    self.update_prompt_match_cursor(&i);
+                }
             }
         }
         self.operations.update_cursor_state(self.cursor.get_state());
     }
 
+    // Moves the cursor to the next word boundary in a given Direction, wrapping onto the next or previous line at either end.
+    // Like any plain movement, this drops a selection started with Shift.
+    pub fn queue_move_word(&mut self, d: Direction) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        self.cursor.release_shift_anchor();
+        let (x, y) = self
+            .operations
+            .word_boundary(self.cursor.get_state().position(), d);
+        self.cursor.teleport(x, y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Moves the cursor to the start of the next word, skipping over any blank lines in between.
+    // Like any plain movement, this drops a selection started with Shift.
+    pub fn queue_next_word(&mut self) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        self.cursor.release_shift_anchor();
+        let (x, y) = self
+            .operations
+            .next_word_start(self.cursor.get_state().position());
+        self.cursor.teleport(x, y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Deletes from the cursor to the next word boundary in a given Direction. At either end of a line, this joins it with
+    // the line next to it. If there's any text selected, the selection is deleted instead.
+    pub fn queue_delete_word(&mut self, d: Direction) {
+        if !matches!(self.mode, WriteMode::Editor) || self.take_selection() {
+            return;
+        }
+        let pos = self.cursor.get_state().position();
+        let boundary = self.operations.word_boundary(pos, d);
+        let sel = Selection::new(pos, boundary);
+        self.operations.delete_selection(sel);
+        let (x, y) = sel.start;
+        self.cursor.teleport(x, y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Moves the cursor to the bracket matching the one it's on, even if the match is off-screen. Does nothing if there's no match.
+    // Like any plain movement, this drops a selection started with Shift.
+    pub fn queue_jump_bracket(&mut self) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        if let Some((x, y)) = self.operations.matching_bracket(self.cursor.get_state()) {
+            self.cursor.release_shift_anchor();
+            self.cursor.teleport(x, y, self.operations.get_text());
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Places the cursor where the mouse was clicked, given as a 1-indexed screen column and row. Clicks outside of the
+    // text area are ignored. drag: whether the mouse is being dragged with the button held down, in which case the text
+    // from where the button was first pressed to the mouse is selected.
+    pub fn queue_click(&mut self, x: u16, y: u16, drag: bool) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        let data = self.operations.get_text();
+        let (col, row) =
+            match self
+                .cursor
+                .screen_to_document(x.saturating_sub(1), y.saturating_sub(1), data)
+            {
+                Some(pos) => pos,
+                None => return,
+            };
+        if drag {
+            self.cursor.set_anchor(true);
+        } else {
+            self.cursor.clear_anchor();
+        }
+        self.cursor.teleport(col, row, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Scrolls the view a few rows up or down for a turn of the mouse wheel. The cursor stays where it is in the document,
+    // unless it would scroll off-screen.
+    pub fn queue_wheel(&mut self, d: Direction) {
+        let delta = match d {
+            Direction::Up => -WHEEL_SCROLL_ROWS,
+            Direction::Down => WHEEL_SCROLL_ROWS,
+            _ => return,
+        };
+        self.cursor.scroll_view(delta, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
     // From a Page InputEvent's Direction, tell the CursorHandler to handle cursor movement.
     // Then give the updated CursorState to the RenderDriver.
     // No support for scrolling in prompt mode yet.
     pub fn queue_scroll(&mut self, d: Direction) {
+        if matches!(self.mode, WriteMode::Editor) {
+            self.cursor.release_shift_anchor();
+        }
         let data = self.operations.get_text();
         let mode = &self.mode;
         match (d, mode) {
@@ -95,44 +537,115 @@ impl RenderController {
 
     // From a Write InputEvent's character, input the given character at the current cursor position.
     // Increment cursor with each write. Update CursorState for the RenderDriver.
-    // Conditonally processes newline inputs if a newline char is input.
+    // Conditonally processes newline inputs if a newline char is input. The cursor lands after the new line's indentation.
     // Depending on current WriteMode, writes are processed using different handler methods.
-    // In prompt mode, newline input is interpreted as prompt confirmation.
+    // In the editor, any selected text is replaced by the write -- except for a tab, which indents the selected lines instead.
+    // In prompt mode, newline input is interpreted as prompt confirmation. Confirming a search leaves the cursor on the current match.
     pub fn queue_write(&mut self, c: char) {
         let mode = &self.mode;
+        let selecting = self
+            .cursor
+            .get_state()
+            .selection()
+            .is_some_and(|sel| !sel.is_empty());
         match (c, mode) {
+            ('\t', WriteMode::Editor) if selecting => self.queue_indent(false),
+            ('\t', WriteMode::Editor) if self.operations.settings().soft_tabs => {
+                let tab_width = self.operations.settings().tab_width;
+                let x = self.cursor.get_state().position().0;
+                self.queue_paste_text(&" ".repeat(tab_width - x % tab_width));
+            }
             ('\n', WriteMode::Editor) => {
-                self.operations.process_newline(self.cursor.get_state());
+                self.take_selection();
+                let col = self.operations.process_newline(self.cursor.get_state());
                 self.cursor
                     .handle_cursor(false, self.cursor.cy + 1, self.operations.get_text());
-                self.cursor
-                    .handle_scroll(true, true, self.operations.get_text());
+                let y = self.cursor.get_state().position().1;
+                self.cursor.teleport(col, y, self.operations.get_text());
                 self.operations.update_cursor_state(self.cursor.get_state());
             }
             (_, WriteMode::Editor) => {
-                self.operations.process_write(self.cursor.get_state(), c);
-                self.cursor
-                    .handle_cursor(true, self.cursor.cx + 1, self.operations.get_text());
+                self.take_selection();
+                let (x, y) = self.cursor.get_state().position();
+                let col = self.operations.process_write(self.cursor.get_state(), c);
+                if col == x + 1 {
+                    self.cursor
+                        .handle_cursor(true, self.cursor.cx + 1, self.operations.get_text());
+                } else {
+                    self.cursor.teleport(col, y, self.operations.get_text());
+                }
                 self.operations.update_cursor_state(self.cursor.get_state());
             }
+            ('\t', WriteMode::Prompt) if self.operations.complete_prompt() => (),
             ('\n', WriteMode::Prompt) | ('\t', WriteMode::Prompt) => {
                 let res = self.operations.process_prompt_confirm();
-                if let Some(PromptResult::FileRename(str)) = res {
-                    self.file_name = str;
-                    self.write_file();
-                    self.mode = WriteMode::Editor;
+                match res {
+                    Some(PromptResult::FileRename(str)) => {
+                        self.file_name = str;
+                        let saved = self.write_file();
+                        self.mode = WriteMode::Editor;
+                        if std::mem::take(&mut self.quit_after_save) && saved {
+                            self.queued_events.push_front(InputEvent::Quit);
+                        }
+                    }
+                    Some(PromptResult::FindConfirm) => self.mode = WriteMode::Editor,
+                    Some(PromptResult::GoTo(target)) => {
+                        self.mode = WriteMode::Editor;
+                        self.queue_goto(&target);
+                    }
+                    Some(PromptResult::Command(line)) => {
+                        self.mode = WriteMode::Editor;
+                        self.run_command(&line);
+                    }
+                    Some(PromptResult::MacroRecord(register)) => {
+                        self.mode = WriteMode::Editor;
+                        self.start_recording(&register);
+                    }
+                    Some(PromptResult::MacroPlay(text)) => {
+                        self.mode = WriteMode::Editor;
+                        self.play_macro(&text);
+                    }
+                    Some(PromptResult::Filter(cmd)) => {
+                        self.mode = WriteMode::Editor;
+                        if !cmd.trim().is_empty() {
+                            self.filter_lines(&cmd);
+                        }
+                    }
+                    Some(PromptResult::ShellInsert(cmd)) => {
+                        self.mode = WriteMode::Editor;
+                        if !cmd.trim().is_empty() {
+                            self.start_shell_insert(&cmd);
+                        }
+                    }
+                    _ => (),
                 }
             }
             (_, WriteMode::Prompt) => {
                 let res = self.operations.process_prompt(c);
-                if let Some(PromptResult::TextSearch(str)) = res {
-                    let results = self.operations.search_text(&str);
-                    let item = results.first();
-                    if let Some(i) = item {
-                        self.update_prompt_match_cursor(i);
-                    }
-                }
+                self.process_search(res);
+            }
+        }
+    }
+
+    // Deletes a character left or right of the cursor like queue_delete does, unless that would delete a line break.
+    // So a run of these stops at the start (or end) of the line, rather than joining lines together. A selection is
+    // deleted as usual.
+    pub fn queue_delete_char(&mut self, d: Direction) {
+        let state = self.cursor.get_state();
+        let (x, y) = state.position();
+        let at_edge = match d {
+            Direction::Left => x == 0,
+            _ => {
+                let len = self
+                    .operations
+                    .get_text()
+                    .get(y)
+                    .map_or(1, |row| row.length());
+                x + 1 >= len as usize
             }
+        };
+        if !at_edge || state.anchor.is_some() || !matches!(self.mode, WriteMode::Editor) {
+            self.queue_delete(d);
         }
     }
 
@@ -140,27 +653,28 @@ impl RenderController {
     // The logic for this operation is a bit more complex with a standard delete (deleting to the left of the cursor)
     // since this involves more fine-grained manipulation of the cursor post-delete.
     // Deletes in prompt mode are significantly simpler, since prompt content is a single TextRow.
+    // If there's any text selected in the editor, the delete removes the selection instead.
     pub fn queue_delete(&mut self, d: Direction) {
+        if matches!(self.mode, WriteMode::Editor) && self.take_selection() {
+            return;
+        }
         let mode = &self.mode;
         match (d, mode) {
             (Direction::Left, WriteMode::Editor) => {
-                if self.cursor.cx > 0 {
+                let (x, y) = self.cursor.get_state().position();
+                if x > 0 {
                     self.operations.process_delete(self.cursor.get_state(), d);
                     self.cursor
                         .handle_cursor(true, self.cursor.cx - 1, self.operations.get_text());
                     self.operations.update_cursor_state(self.cursor.get_state());
-                } else if self.cursor.cx == 0 && self.cursor.cy > 0 {
-                    let old_adj_len = self
-                        .operations
-                        .get_length_at_line((self.cursor.cy - 1) as usize);
+                } else if y > 0 {
+                    let old_adj_len = self.operations.get_length_at_line(y - 1);
                     self.operations
                         .process_wrap_delete(self.cursor.get_state(), d);
 
                     // update cursor to jump to previous line, set cx to end of previous line's original contents
                     let data = self.operations.get_text();
-                    self.cursor.handle_cursor(false, self.cursor.cy - 1, data);
-                    self.cursor
-                        .handle_cursor(true, old_adj_len.try_into().unwrap(), data);
+                    self.cursor.teleport(old_adj_len, y - 1, data);
                     self.operations.update_cursor_state(self.cursor.get_state())
                 }
             }
@@ -172,33 +686,80 @@ impl RenderController {
             }
             (Direction::Left, WriteMode::Prompt) => {
                 let res = self.operations.process_prompt_delete(true);
-                if let Some(PromptResult::TextSearch(str)) = res {
-                    let results = self.operations.search_text(&str);
-                    let item = results.first();
-                    if let Some(i) = item {
-                        self.update_prompt_match_cursor(i);
-                    }
-                }
+                self.process_search(res);
             }
             (Direction::Right, WriteMode::Prompt) => {
                 let res = self.operations.process_prompt_delete(false);
-                if let Some(PromptResult::TextSearch(str)) = res {
-                    let results = self.operations.search_text(&str);
-                    let item = results.first();
-                    if let Some(i) = item {
-                        self.update_prompt_match_cursor(i);
+                self.process_search(res);
+            }
+            _ => (),
+        }
+    }
+
+    // Repeats the last search in the given direction. In the editor, this searches from the cursor using the last confirmed query.
+    // While a search prompt is open, this just steps through the current results (same as the arrow keys).
+    // A search that finds nothing stops any macro being replayed.
+    pub fn queue_find_repeat(&mut self, forward: bool) {
+        match self.mode {
+            WriteMode::Editor => {
+                match self
+                    .operations
+                    .repeat_search(self.cursor.get_state(), forward)
+                {
+                    Some(i) => self.update_prompt_match_cursor(&i),
+                    None => self.stop_replay("search failed."),
+                }
+            }
+            WriteMode::Prompt => {
+                let d = if forward {
+                    Direction::Down
+                } else {
+                    Direction::Up
+                };
+                self.queue_move(d);
+            }
+        }
+    }
+
+    // Starts an incremental search, forward or backward from the cursor. While a search prompt is open, this steps
+    // through its results instead, like emacs' C-s and C-r.
+    pub fn queue_search(&mut self, forward: bool) {
+        match self.mode {
+            WriteMode::Editor => {
+                self.start_prompt(InputEvent::Find);
+                self.search_backward = !forward;
+            }
+            WriteMode::Prompt => self.queue_find_repeat(forward),
+        }
+    }
+
+    // Runs an incremental search when the prompt hands back a query, starting from wherever the cursor was when the prompt opened.
+    // If nothing matches, the cursor goes back to where it started, and any macro being replayed is stopped.
+    fn process_search(&mut self, res: Option<PromptResult>) {
+        if let Some(PromptResult::TextSearch(str)) = res {
+            let origin = self.cursor.get_saved_state().position();
+            let found = match self.operations.search_text(&str, origin) {
+                // a backward search wants the match before the first one after the cursor
+                Some(_) if self.search_backward => self.operations.update_prompt_match_idx(-1),
+                found => found,
+            };
+            match found {
+                Some(i) => self.update_prompt_match_cursor(&i),
+                None => {
+                    self.cursor.restore_state();
+                    self.operations.update_cursor_state(self.cursor.get_state());
+                    if !str.is_empty() {
+                        self.stop_replay("search failed.");
                     }
                 }
             }
-            _ => (),
         }
     }
 
+    // Moves the cursor to the start of a search match, scrolling the match into view if it's off-screen.
     fn update_prompt_match_cursor(&mut self, res: &SearchItem) {
-        self.cursor
-            .handle_cursor(false, res.cy, self.operations.get_text());
-        self.cursor
-            .handle_cursor(true, res.cx, self.operations.get_text());
+        let (x, y) = res.position();
+        self.cursor.teleport(x, y, self.operations.get_text());
         self.operations.update_cursor_state(self.cursor.get_state());
     }
 
@@ -222,12 +783,14 @@ impl RenderController {
 
     // Tells the OperationHandler to write current terminal contents to a file with a known name.
     // If the file is untitled, the controller should start rerouting writes to PromptProc. The OH will render a save-as prompt.
-    pub fn write_file(&mut self) {
+    // Returns whether the file was written.
+    pub fn write_file(&mut self) -> bool {
         let save_as = self.operations.check_file_name();
         if !save_as {
-            self.operations.write_file(&self.file_name);
+            self.operations.write_file(&self.file_name).is_ok()
         } else {
-            self.start_prompt(InputEvent::Save)
+            self.start_prompt(InputEvent::Save);
+            false
         }
     }
 
@@ -238,19 +801,568 @@ impl RenderController {
     pub fn start_prompt(&mut self, kind: InputEvent) {
         if !matches!(self.mode, WriteMode::Prompt) {
             self.mode = WriteMode::Prompt;
+            self.search_backward = false;
             self.operations.initialize_prompt(kind);
             self.cursor.save_state();
         }
     }
 
     // Called whenever a prompt is dismissed or exited. Set back WriteMode, clear any statuses, wipe the PromptProc.
+    // Cancelling a Save As prompt opened by :wq cancels the quit too.
+    // If there's no prompt open, this just clears the selection and any search matches that are still highlighted.
     pub fn exit_prompt(&mut self) {
+        if !matches!(self.mode, WriteMode::Prompt) {
+            if let Some(job) = self.shell_job.take() {
+                let cmd = job.command().to_string();
+                self.operations
+                    .report_shell(StatusContent::ShellCancelled(cmd));
+            }
+            self.operations.clear_search_highlight();
+            self.cursor.clear_anchor();
+            self.operations.update_cursor_state(self.cursor.get_state());
+            return;
+        }
         self.mode = WriteMode::Editor;
+        self.quit_after_save = false;
         self.operations.wipe_prompt();
         self.cursor.restore_state();
         self.operations.update_cursor_state(self.cursor.get_state());
     }
 
+    // Moves the cursor to a go-to-line target, recentering the view on it if it's off-screen.
+    // Relative targets are measured from wherever the cursor was when the prompt was opened. An empty target does nothing.
+    fn queue_goto(&mut self, target: &str) {
+        if target.trim().is_empty() {
+            return;
+        }
+        let origin = self.cursor.get_saved_state().position();
+        if let Some((x, y)) = self.operations.resolve_goto(target, origin) {
+            self.cursor.release_shift_anchor();
+            self.cursor.teleport(x, y, self.operations.get_text());
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Runs a command typed into the command line. Commands that can be bound to a key are queued up for the editor to
+    // handle next, just as if their key had been pressed. Anything else is handled here.
+    fn run_command(&mut self, line: &str) {
+        match commands::parse(line) {
+            Ok(Command::Action(evt)) => self.queued_events.push_front(evt),
+            Ok(Command::Write(None)) => {
+                self.write_file();
+            }
+            Ok(Command::Write(Some(path))) => {
+                // the file only takes on its new name once it's been written there
+                if self.operations.write_file(&path).is_ok() {
+                    self.operations.set_file_name(&path);
+                    self.file_name = path;
+                }
+            }
+            Ok(Command::WriteQuit) => {
+                // an untitled file asks for a name first, and quits once it's been saved under it
+                if self.operations.check_file_name() {
+                    self.quit_after_save = true;
+                    self.write_file();
+                } else if self.write_file() {
+                    self.queued_events.push_front(InputEvent::Quit);
+                }
+            }
+            Ok(Command::Edit(path, force)) => self.open_file(&path, force),
+            Ok(Command::Set(key, value)) => self.operations.set_option(&key, &value),
+            Ok(Command::GoTo(target)) => self.queue_goto(&target),
+            Ok(Command::Replace(find, replace)) => {
+                let (x, y) = self.cursor.get_state().position();
+                self.operations.checkpoint((x, y), None);
+                self.operations.replace_all(&find, &replace);
+                self.cursor.clear_anchor();
+                self.cursor.teleport(x, y, self.operations.get_text());
+                self.operations.update_cursor_state(self.cursor.get_state());
+            }
+            Ok(Command::Sort(options)) => {
+                self.rearrange_lines(|mut rows| {
+                    lines::sort(&mut rows, &options);
+                    Ok(rows)
+                });
+            }
+            Ok(Command::Unique) => self.rearrange_lines(|rows| Ok(lines::unique(rows))),
+            Ok(Command::Reverse) => {
+                self.rearrange_lines(|rows| Ok(rows.into_iter().rev().collect()))
+            }
+            Ok(Command::Filter(cmd)) => self.filter_lines(&cmd),
+            Err(e) => self.operations.report_command_error(e),
+        }
+    }
+
+    // Rearranges every line touched by the selection, or every line in the file if nothing is selected -- by sorting them,
+    // or running them through a shell command. If that fails, the lines are left alone and the reason is shown.
+    // Otherwise, this is a single change, undone all at once. The cursor stays on the same row, if it's still there.
+    fn rearrange_lines(
+        &mut self,
+        rearrange: impl FnOnce(Vec<String>) -> Result<Vec<String>, String>,
+    ) {
+        let state = self.cursor.get_state();
+        let (first, last) = match state.selection() {
+            Some(sel) if !sel.is_empty() => sel.rows(),
+            _ => (0, self.operations.get_text().len().saturating_sub(1)),
+        };
+        let rows = match rearrange(self.operations.line_texts(first, last)) {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.operations
+                    .report_command_error(format!("Command failed: {}", e));
+                return;
+            }
+        };
+        let (x, y) = state.position();
+        self.operations.checkpoint((x, y), None);
+        self.operations.replace_lines(first, last, rows);
+        self.cursor.clear_anchor();
+        self.cursor.teleport(x, y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Runs the selected lines (or the whole file) through a shell command, replacing them with what it prints.
+    fn filter_lines(&mut self, cmd: &str) {
+        self.rearrange_lines(|rows| {
+            let input = rows.join("\n") + "\n";
+            let output = shell::filter(cmd, &input)?;
+            let output = output.strip_suffix('\n').unwrap_or(&output);
+            Ok(output.split('\n').map(String::from).collect())
+        });
+    }
+
+    // Starts running a shell command in the background. Its output is inserted once it's done, by check_shell_job.
+    // Only one can run at a time, so any that's still running is stopped.
+    fn start_shell_insert(&mut self, cmd: &str) {
+        self.shell_job = None;
+        match ShellJob::start(cmd) {
+            Ok(job) => {
+                self.shell_job = Some(job);
+                self.shell_insert_at = self.cursor.get_state().position();
+                self.operations
+                    .report_shell(StatusContent::ShellRunning(cmd.to_string()));
+            }
+            Err(e) => self
+                .operations
+                .report_command_error(format!("Command failed: {}", e)),
+        }
+    }
+
+    // Checks on the shell command running in the background, if there is one. Once it's done, its output is inserted where
+    // the cursor was when it started (less one trailing newline, so that `date` doesn't leave an empty line behind).
+    // Nothing is inserted for a command with no output.
+    // Any selection made in the meantime is left as it is. The cursor and anchor are moved along if the output goes in
+    // before them, so that they stay on the same text. Without a selection, a cursor that hasn't moved ends up after the
+    // output, like after a paste.
+    // While a prompt is open, the output waits, so that it doesn't end up in the prompt.
+    pub fn check_shell_job(&mut self) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        let result = match self.shell_job.as_mut().and_then(|job| job.poll()) {
+            Some(result) => result,
+            None => return,
+        };
+        self.shell_job = None;
+        let (output, truncated) = match result {
+            Ok(output) => output,
+            Err(e) => {
+                self.operations
+                    .report_command_error(format!("Command failed: {}", e));
+                return;
+            }
+        };
+        let text = output.strip_suffix('\n').unwrap_or(&output);
+        if text.is_empty() {
+            self.operations
+                .report_shell(StatusContent::ShellInserted(0, truncated));
+            return;
+        }
+        let state = self.cursor.get_state();
+        self.operations.checkpoint(state.position(), None);
+        // the document might have gotten shorter since the command was started
+        let (x, y) = self.shell_insert_at;
+        let text_rows = self.operations.get_text();
+        let y = y.min(text_rows.len());
+        let len = text_rows.get(y).map_or(0, |r| r.length() as usize);
+        let at = (x.min(len), y);
+        let end = self.operations.insert_text(at, text);
+        // a selection that ends right at the insertion point stays put, so that the output lands outside of it
+        let (cx, cy) = state.position();
+        let ends_at = match state.anchor {
+            Some((ax, ay)) => (ay, ax).min((cy, cx)) < (at.1, at.0),
+            None => false,
+        };
+        let shift = |(col, row): (usize, usize)| {
+            if (row, col) < (at.1, at.0) || (ends_at && (col, row) == at) {
+                (col, row)
+            } else if row == at.1 {
+                (end.0 + col - at.0, end.1)
+            } else {
+                (col, row + end.1 - at.1)
+            }
+        };
+        if let Some(a) = state.anchor {
+            self.cursor.move_anchor(shift(a));
+        }
+        let (new_x, new_y) = shift(state.position());
+        self.cursor
+            .teleport(new_x, new_y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+        let lines = text.split('\n').count();
+        self.operations
+            .report_shell(StatusContent::ShellInserted(lines, truncated));
+    }
+
+    // Opens a different file in place of the current one. A file that doesn't exist yet is opened empty, and created on save.
+    // Refuses to throw away unsaved changes, unless forced to.
+    fn open_file(&mut self, path: &str, force: bool) {
+        if self.operations.is_dirty() && !force {
+            let msg = "The file has unsaved changes. Use e! to open another file anyway.";
+            self.operations.report_command_error(msg.to_string());
+            return;
+        }
+        let lines: Vec<String> = match fs::read_to_string(path) {
+            Ok(text) => text.lines().map(String::from).collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                self.operations
+                    .report_command_error(format!("Couldn't open {}: {}", path, e));
+                return;
+            }
+        };
+        self.operations.set_file_name(path);
+        self.file_name = path.to_string();
+        self.queue_text_upload(&lines);
+        self.shell_job = None;
+        self.operations.clear_search_highlight();
+        self.cursor.clear_anchor();
+        self.cursor.teleport(0, 0, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Whether a prompt is open.
+    pub fn is_prompting(&self) -> bool {
+        matches!(self.mode, WriteMode::Prompt)
+    }
+
+    // Shows the current vim mode in the status bar, if modal editing is on.
+    pub fn set_mode_label(&mut self, label: Option<String>) {
+        self.operations.set_mode_label(label);
+    }
+
+    // Hands over the next queued up event, if there is one. Once a replay runs out of events, it's over.
+    // A replay that runs on for too long (most likely a macro that replays itself) is stopped.
+    pub fn take_queued_event(&mut self) -> Option<InputEvent> {
+        if self.replaying {
+            self.replayed += 1;
+            if self.replayed > REPLAY_LIMIT {
+                self.stop_replay("too many events.");
+            }
+        }
+        let evt = self.queued_events.pop_front();
+        if evt.is_none() && self.replaying {
+            self.replaying = false;
+            self.operations.end_group();
+        }
+        evt
+    }
+
+    // Gets ready for an event that's about to be handled. Events are recorded into the macro being recorded (unless
+    // they're being replayed), and an undo checkpoint is taken before anything that might change the text.
+    // Typing into a block selection splits it into a cursor on each row. Anything that can't be done at every cursor gets
+    // rid of the extra ones first.
+    // Typing a run of word characters, or deleting one character after another, is undone all at once.
+    pub fn before_event(&mut self, evt: &InputEvent) {
+        if !matches!(evt, InputEvent::KillLine) {
+            self.operations.break_kill();
+        }
+        if !self.replaying && !matches!(evt, InputEvent::MacroRecord) {
+            self.macros.record(evt);
+        }
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        if !self.spans_cursors(evt) && !keeps_cursors(evt) {
+            self.operations.clear_extra_cursors();
+        }
+        let pos = self.cursor.get_state().position();
+        match evt {
+            InputEvent::Write(c) if c.is_alphanumeric() || *c == '_' => {
+                self.operations.checkpoint(pos, Some("write"))
+            }
+            InputEvent::Delete(_) | InputEvent::DeleteChar(_) => {
+                self.operations.checkpoint(pos, Some("delete"))
+            }
+            InputEvent::Write(_)
+            | InputEvent::Paste(_)
+            | InputEvent::DeleteWord(_)
+            | InputEvent::Dedent
+            | InputEvent::Cut
+            | InputEvent::KillLine
+            | InputEvent::Yank
+            | InputEvent::YankAfter
+            | InputEvent::YankCycle
+            | InputEvent::ToggleComment
+            | InputEvent::DuplicateLines
+            | InputEvent::MoveLines(_)
+            | InputEvent::DeleteLines
+            | InputEvent::JoinLines => self.operations.checkpoint(pos, None),
+            _ => self.operations.break_merge(),
+        }
+        self.split_block(evt);
+    }
+
+    // Typing or pasting into a block selection puts the same text on every row of the block. The block's text is deleted,
+    // and a cursor is put at its left edge on every row, so that the edit (and any after it) is made at each of them.
+    // Deleting from a block with no width deletes next to each of those cursors in the same way.
+    fn split_block(&mut self, evt: &InputEvent) {
+        let state = self.cursor.get_state();
+        let block = match self.operations.block(state) {
+            Some(block) => block,
+            None => return,
+        };
+        let inserts = matches!(evt, InputEvent::Write(_) | InputEvent::Paste(_));
+        let splits = match evt {
+            InputEvent::Delete(_) | InputEvent::DeleteChar(_) | InputEvent::DeleteWord(_) => {
+                block.is_empty()
+            }
+            _ => inserts,
+        };
+        if !splits {
+            return;
+        }
+        self.cursor.clear_anchor();
+        let (x, y) = self
+            .operations
+            .block_to_cursors(block, state.position().1, inserts);
+        self.cursor.teleport(x, y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Undoes (or redoes) the last change to the text, and puts the cursor back where it was.
+    pub fn queue_undo(&mut self, redo: bool) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        let pos = self.cursor.get_state().position();
+        if let Some((x, y)) = self.operations.undo(pos, redo) {
+            self.cursor.clear_anchor();
+            self.cursor.teleport(x, y, self.operations.get_text());
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+    }
+
+    // Starts recording a macro (after asking for a register to record into), or stops the one being recorded.
+    pub fn queue_macro_record(&mut self) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        match self.macros.stop() {
+            Some((name, count)) => {
+                self.operations.set_macro_label(None);
+                self.operations
+                    .report_macro(StatusContent::MacroSaved(name, count));
+            }
+            None => self.start_prompt(InputEvent::MacroRecord),
+        }
+    }
+
+    // Starts recording into the register typed into the prompt.
+    fn start_recording(&mut self, register: &str) {
+        let name = self.macros.start(register);
+        self.operations.set_macro_label(Some(name.clone()));
+        self.operations
+            .report_macro(StatusContent::MacroRecording(name));
+    }
+
+    // Replays the macro typed into the prompt, as many times as asked. The whole replay is a single undo group.
+    // Its events go to the front of the queue, so that a macro which replays another one runs it in place.
+    // A replay that would run more than REPLAY_LIMIT events isn't started at all.
+    fn play_macro(&mut self, text: &str) {
+        let (register, count) = match macros::parse_play(text) {
+            Some(parsed) => parsed,
+            None => {
+                let msg = format!("Not a register and count: {}", text.trim());
+                self.operations
+                    .report_macro(StatusContent::MacroStopped(msg));
+                return;
+            }
+        };
+        let events = match self.macros.get(&register) {
+            Ok((_, events)) => events,
+            Err(name) => {
+                self.operations
+                    .report_macro(StatusContent::MacroMissing(name));
+                return;
+            }
+        };
+        // a replay too long to run is turned down before anything is queued up
+        let ran = if self.replaying { self.replayed } else { 0 };
+        let pending = ran + self.queued_events.len();
+        if count.saturating_mul(events.len()).saturating_add(pending) > REPLAY_LIMIT {
+            if self.replaying {
+                self.stop_replay("too many events.");
+            } else {
+                self.operations
+                    .report_macro(StatusContent::MacroStopped("too many events.".to_string()));
+            }
+            return;
+        }
+        if !self.replaying {
+            self.replaying = true;
+            self.replayed = 0;
+            self.operations
+                .begin_group(self.cursor.get_state().position());
+        }
+        for _ in 0..count {
+            for evt in events.iter().rev() {
+                self.queued_events.push_front(evt.clone());
+            }
+        }
+    }
+
+    // Stops a replay early, throwing away the rest of its events. Any prompt that it left open is closed.
+    fn stop_replay(&mut self, reason: &str) {
+        if !self.replaying {
+            return;
+        }
+        self.queued_events.clear();
+        if matches!(self.mode, WriteMode::Prompt) {
+            self.exit_prompt();
+        }
+        self.operations
+            .report_macro(StatusContent::MacroStopped(reason.to_string()));
+    }
+
+    // Whether an event should be handled at every cursor, rather than just the main one. Only typing, deleting, pasting and
+    // plain movement are.
+    pub fn spans_cursors(&self, evt: &InputEvent) -> bool {
+        matches!(self.mode, WriteMode::Editor)
+            && self.operations.has_extra_cursors()
+            && matches!(
+                evt,
+                InputEvent::Write(_)
+                    | InputEvent::Paste(_)
+                    | InputEvent::Delete(_)
+                    | InputEvent::DeleteChar(_)
+                    | InputEvent::DeleteWord(_)
+                    | InputEvent::Yank
+                    | InputEvent::YankAfter
+                    | InputEvent::Move(_)
+                    | InputEvent::MoveWord(_)
+                    | InputEvent::NextWord
+                    | InputEvent::Page(Direction::Left)
+                    | InputEvent::Page(Direction::Right)
+            )
+    }
+
+    // Handles an event at every cursor, from the end of the document up. The main cursor is moved to each one in turn,
+    // and where it ends up is measured from the end of the document, which edits made further up can't change.
+    // Once they've all been handled, cursors that ran into each other are merged.
+    pub fn at_every_cursor(&mut self, mut handle: impl FnMut(&mut Self)) {
+        let main = self.cursor.get_state().position();
+        self.cursor.save_state();
+        self.operations.hold_brackets(true);
+        let mut offsets = Vec::new();
+        for (is_main, (x, y)) in self.operations.cursors_in_reverse(main) {
+            self.cursor.teleport(x, y, self.operations.get_text());
+            handle(self);
+            let pos = self.cursor.get_state().position();
+            offsets.push((is_main, self.operations.offset_from_end(pos)));
+        }
+        let (x, y) = self.operations.settle_cursors(offsets);
+        self.operations.hold_brackets(false);
+        // go back to the original view, so that it only scrolls if the main cursor has moved off-screen
+        self.cursor.restore_state();
+        self.cursor.teleport(x, y, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Adds a cursor on the line above or below the main one, at the same column (or the end of the line, if it's shorter).
+    pub fn queue_add_cursor(&mut self, d: Direction) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        let (x, y) = self.cursor.get_state().position();
+        let rows = self.operations.get_text().len();
+        let y = match d {
+            Direction::Up if y > 0 => y - 1,
+            Direction::Down if y + 1 < rows => y + 1,
+            _ => return,
+        };
+        let x = x.min(self.operations.get_length_at_line(y));
+        self.move_main_cursor((x, y));
+    }
+
+    // Adds a cursor at the next occurrence of the word under the main cursor, at the same spot within the word.
+    // Only whole words count, and the search wraps around the document. Occurrences that already have a cursor are skipped.
+    pub fn queue_add_cursor_next(&mut self) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        let (x, y) = self.cursor.get_state().position();
+        let (start, word) = match self.operations.word_at((x, y)) {
+            Some(found) => found,
+            None => return,
+        };
+        let offset = x - start;
+        let mut candidates = Vec::new();
+        for (mx, my) in self.operations.match_positions(Some(&word)) {
+            let whole = self
+                .operations
+                .word_at((mx, my))
+                .is_some_and(|(s, w)| s == mx && w == word);
+            let taken =
+                (mx + offset, my) == (x, y) || self.operations.has_cursor_at((mx + offset, my));
+            if whole && !taken {
+                candidates.push((mx + offset, my));
+            }
+        }
+        let next = candidates
+            .iter()
+            .find(|(cx, cy)| (*cy, *cx) > (y, x))
+            .or(candidates.first());
+        if let Some(&pos) = next {
+            self.move_main_cursor(pos);
+        }
+    }
+
+    // Puts a cursor at every match of the last search. The main cursor goes to the first match at or after it.
+    pub fn queue_add_cursor_matches(&mut self) {
+        if !matches!(self.mode, WriteMode::Editor) {
+            return;
+        }
+        let matches = self.operations.match_positions(None);
+        let (x, y) = self.cursor.get_state().position();
+        let main = match matches.iter().find(|(mx, my)| (*my, *mx) >= (y, x)) {
+            Some(pos) => *pos,
+            None => match matches.first() {
+                Some(pos) => *pos,
+                None => return,
+            },
+        };
+        self.operations.clear_extra_cursors();
+        for pos in matches.into_iter().filter(|pos| *pos != main) {
+            self.operations.add_cursor(pos);
+        }
+        self.cursor.clear_anchor();
+        self.cursor
+            .teleport(main.0, main.1, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
+    // Moves the main cursor to a new position, leaving an extra cursor behind where it was.
+    fn move_main_cursor(&mut self, pos: (usize, usize)) {
+        let old = self.cursor.get_state().position();
+        self.operations.add_cursor(old);
+        self.operations.remove_cursor(pos);
+        self.cursor.clear_anchor();
+        self.cursor
+            .teleport(pos.0, pos.1, self.operations.get_text());
+        self.operations.update_cursor_state(self.cursor.get_state());
+    }
+
     // Parse a vec of strings into a vec of TextRows.
     // Pass this vec of TextRows to the RenderDriver.
     pub fn queue_text_upload(&mut self, vec: &Vec<String>) {
@@ -268,6 +1380,13 @@ impl RenderController {
         self.operations.complete_init();
     }
 
+    // Shows any problems found in config files at startup, in place of the help message.
+    pub fn report_config_errors(&mut self, errors: Vec<String>) {
+        if !errors.is_empty() {
+            self.operations.report_config_errors(errors);
+        }
+    }
+
     // Tell the RenderDriver to shutdown the editor.
     // Return a bool that represents whether to shutdown the editor.
     pub fn exit(&mut self) -> bool {
@@ -275,8 +1394,68 @@ impl RenderController {
         self.operations.exit()
     }
 
+    // Saves the file if autosave is on, and it's been modified for longer than the autosave interval.
+    // Untitled files are left alone, since saving them needs a prompt. So are files with a prompt open over them.
+    pub fn autosave(&mut self) {
+        let interval = match self.autosave_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        if !self.operations.is_dirty() {
+            self.last_save = Instant::now();
+        } else if self.last_save.elapsed() >= interval
+            && matches!(self.mode, WriteMode::Editor)
+            && !self.operations.check_file_name()
+        {
+            // a file that can't be written has its error shown, and is tried again after another interval
+            let _ = self.operations.write_file(&self.file_name);
+            self.last_save = Instant::now();
+        }
+    }
+
     // Tell the RenderDriver to continue processing.
+    // The line number gutter can grow as lines are added, so the cursor is told how wide it is before each draw. It's told the
+    // tab width too, which can change with :set or when another file is opened.
     pub fn tick_screen(&mut self) -> Result<(), Error> {
+        let gutter = self.operations.gutter_width();
+        if self.cursor.set_gutter(gutter, self.operations.get_text()) {
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
+        let tab_width = self.operations.settings().tab_width;
+        if self
+            .cursor
+            .set_tab_width(tab_width, self.operations.get_text())
+        {
+            self.operations.update_cursor_state(self.cursor.get_state());
+        }
         self.operations.tick_screen()
     }
 }
+
+// Whether an event leaves any extra cursors alone, even though it's only handled at the main cursor.
+fn keeps_cursors(evt: &InputEvent) -> bool {
+    matches!(
+        evt,
+        InputEvent::AddCursor(_)
+            | InputEvent::AddCursorNext
+            | InputEvent::AddCursorMatches
+            | InputEvent::Save
+            | InputEvent::Scroll(_)
+            | InputEvent::Find
+            | InputEvent::FindNext
+            | InputEvent::FindPrev
+            | InputEvent::Search(_)
+            | InputEvent::MacroRecord
+            | InputEvent::MacroPlay
+            | InputEvent::CommandLine
+            | InputEvent::Quit
+    )
+}
+
+// CONSTS //
+
+// How many rows a single turn of the mouse wheel scrolls by.
+const WHEEL_SCROLL_ROWS: i16 = 3;
+
+// How many events a single replay can run, before it's assumed to be stuck replaying itself.
+const REPLAY_LIMIT: usize = 100_000;
@@ -1,21 +1,34 @@
 use crate::{
+    config::{self, Config, Settings},
     data::{
         enums::StatusContent,
-        payload::{CursorState, DirtyStatus, StatusMessage},
-        textrow::TextRow,
+        language::Language,
+        payload::{Block, CursorState, DirtyStatus, StatusMessage},
+        textrow::{cell_width, TextRow},
     },
+    gfx::theme::Theme,
     utils,
 };
-use std::io::{stdout, BufWriter, Error, Stdout, Write};
+use std::{
+    io::{stdout, BufWriter, Error, Stdout, Write},
+    ops::Range,
+};
 use termion::{
-    color::{self, Rgb},
+    color,
     raw::{IntoRawMode, RawTerminal},
+    style,
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 // RenderDriver. Primarily responsible for everything we draw to the editor window.
 // Contains an understanding of editor window size, based upon window size at program initialization.
 // Contains a BufWriter for writing efficiently to stdout in Raw Mode.
 // Might contain a vector of TextRows, and holds a reference to the current state of the CursorHandler.
+// bracket_match holds the document positions of the bracket under the cursor and its match, when there is one.
+// config holds the user's settings, and settings the ones that apply to the open file.
+// mode_label holds the current vim mode, when modal editing is on. It's shown at the start of the status bar.
+// macro_label holds the register that a macro is being recorded into, while one is. It's shown next to the mode.
+// extra_cursors holds the document positions of any cursors besides the main one. They're drawn as highlighted cells.
 pub struct RenderDriver {
     rows: u16,
     cols: u16,
@@ -23,42 +36,66 @@ pub struct RenderDriver {
     text: Vec<TextRow>,
     cursor: CursorState,
     file_name: String,
+    language: &'static Language,
     status_info: String,
     status_message: StatusMessage,
     mod_status: DirtyStatus,
-    status_kind: StatusContent,
+    search_highlight: Option<String>,
+    bracket_match: Option<[(usize, usize); 2]>,
+    config: Config,
+    settings: Settings,
+    mode_label: Option<String>,
+    macro_label: Option<String>,
+    extra_cursors: Vec<(usize, usize)>,
 }
 
 impl RenderDriver {
     // A RenderDriver is created with a fresh CursorState.
     // Initially, text is set to an empty vector of textrows. This is replaced with text if the program is run with a file path.
     // Filename and statusinfo are also set to empty values. These are replaced if the program is run with a file path.
-    pub fn new(cursor: CursorState) -> Self {
+    // Turns on bracketed paste mode, so that pasted text can be told apart from typing, and mouse reporting.
+    pub fn new(cursor: CursorState, config: Config) -> Self {
         let size_rc = utils::get_window_size();
+        let mut buf = BufWriter::new(stdout().into_raw_mode().unwrap());
+        write!(buf, "{}{}", BRACKETED_PASTE_ON, MOUSE_ON).expect(WRITE_ERR_MSG);
         Self {
             rows: size_rc.rows,
             cols: size_rc.cols,
-            buf: BufWriter::new(stdout().into_raw_mode().unwrap()),
+            buf,
             text: vec![TextRow::default()],
             cursor,
             file_name: "".to_string(),
+            language: Language::detect(""),
             status_info: "".to_string(),
-            status_message: StatusMessage::new(false),
+            status_message: StatusMessage::new(false, config.status_timeout),
             mod_status: DirtyStatus::new(),
-            // arbitrary default
-            status_kind: StatusContent::Help,
+            search_highlight: None,
+            bracket_match: None,
+            settings: config.for_file(""),
+            config,
+            mode_label: None,
+            macro_label: None,
+            extra_cursors: Vec::new(),
         }
     }
 
     // Draw the editor's status bar, which spans the bottom-most line of the editor.
     // Contains the filename, # of lines in the file, and the current line.
     fn draw_status_bar(&mut self) {
+        let mut label = match &self.mode_label {
+            Some(mode) => format!("[{}] ", mode),
+            None => String::new(),
+        };
+        if let Some(register) = &self.macro_label {
+            label.push_str(&format!("[REC {}] ", register));
+        }
         write!(self.buf, "{}", termion::clear::CurrentLine).expect(WRITE_ERR_MSG);
         write!(
             self.buf,
-            "{}{}{}",
+            "{}{}{}{}",
             color::Bg(color::White),
             color::Fg(color::Black),
+            label,
             self.status_info
         )
         .unwrap();
@@ -66,7 +103,7 @@ impl RenderDriver {
         // only exclude length of text written -- termion:color borks str len
         for _n in 0..self
             .cols
-            .wrapping_sub(self.status_info.len().try_into().unwrap())
+            .wrapping_sub((label.len() + self.status_info.len()).try_into().unwrap())
             .wrapping_sub((self.cursor.line_num().len()).try_into().unwrap())
         {
             write!(self.buf, " ").unwrap();
@@ -113,29 +150,39 @@ impl RenderDriver {
             self.rows - 1
         };
 
-        // black
-        let other_fg = color::Fg(Rgb(255, 255, 255));
+        let theme = self.config.theme;
+        let other_fg = color::Fg(theme.text);
+        let gutter = self.gutter_width();
         let mut multiline_comment = false;
 
         for n in 0..end {
             // clear the current line
             write!(self.buf, "{}", termion::clear::CurrentLine).expect(WRITE_ERR_MSG);
             let row_idx = n.wrapping_add(self.cursor.row_offset as u16);
+            if gutter > 0 {
+                self.draw_line_number(row_idx as usize, gutter);
+            }
             // render text if necessary, else render edge (or blank space for the final line)
             if row_idx < self.text.len() as u16 {
+                // Find anything that should be highlighted on this row -- extra cursors, selected text, or search matches.
+                let mut highlights = self.find_cursors(row_idx as usize);
+                highlights.extend(self.find_selection(row_idx as usize));
+                highlights.extend(self.find_highlights(row_idx as usize));
+                highlights.extend(self.find_brackets(row_idx as usize));
                 let render_str = self.text[row_idx as usize]
-                    .substring(self.cursor.col_offset)
-                    .truncate(self.cols);
-                let tokens: Vec<String> = tokenize_preserve_whitespace(&render_str.raw_text);
+                    .clip(
+                        self.cursor.col_offset as usize,
+                        (self.cols - gutter) as usize,
+                        self.settings.tab_width,
+                    )
+                    .to_string();
+                let tokens: Vec<String> = tokenize_preserve_whitespace(&render_str);
 
-                // If we're in a find state, we need to highlight the search query.
-                let q = if let StatusContent::Find(q) = &self.status_kind {
-                    q
-                } else {
-                    ""
-                };
+                let mut out = SpanWriter::new(&mut self.buf, &highlights, theme);
                 multiline_comment =
-                    process_tokens(&mut self.buf, tokens, q, &self.file_name, multiline_comment);
+                    process_tokens(&mut out, tokens, self.language, multiline_comment);
+                out.pad_cursors();
+                drop(out);
                 writeln!(self.buf, "\r{}", other_fg).expect(WRITE_ERR_MSG);
             } else {
                 writeln!(self.buf, "~\r{}", other_fg).expect(WRITE_ERR_MSG);
@@ -148,10 +195,165 @@ impl RenderDriver {
         self.reset_color();
     }
 
+    // Returns how many columns the line number gutter takes up -- enough for the largest line number (and at least 3 digits),
+    // plus a space. 0 if line numbers are turned off.
+    pub fn gutter_width(&self) -> u16 {
+        if self.settings.line_numbers {
+            self.text.len().to_string().len().max(3) as u16 + 1
+        } else {
+            0
+        }
+    }
+
+    // Draws the line number for a row into the gutter. Rows past the end of the document get a blank gutter.
+    fn draw_line_number(&mut self, idx: usize, gutter: u16) {
+        let width = gutter as usize - 1;
+        let num = if idx < self.text.len() {
+            (idx + 1).to_string()
+        } else {
+            String::new()
+        };
+        write!(
+            self.buf,
+            "{}{:>width$} {}",
+            color::Fg(self.config.theme.line_number),
+            num,
+            color::Fg(self.config.theme.text),
+            width = width
+        )
+        .expect(WRITE_ERR_MSG);
+    }
+
+    // Converts a grapheme index on a given row into a screen column, relative to the visible part of the row.
+    // Indices that are scrolled off to the left land on the first column.
+    fn screen_col(&self, idx: usize, x: usize) -> usize {
+        let offset = self.cursor.col_offset as usize;
+        let tab_width = self.settings.tab_width;
+        match self.text.get(idx) {
+            Some(row) => {
+                row.display_col(x.max(offset), tab_width) - row.display_col(offset, tab_width)
+            }
+            None => x.saturating_sub(offset),
+        }
+    }
+
+    // Finds the part of a given row that's selected, if any, as a range of screen columns.
+    fn find_selection(&self, idx: usize) -> Vec<(usize, usize, Highlight)> {
+        if let Some(block) = self.cursor.block(&self.text, self.settings.tab_width) {
+            return self.find_block(block, idx);
+        }
+        let sel = match self.cursor.selection() {
+            Some(sel) if !sel.is_empty() && sel.start.1 <= idx && idx <= sel.end.1 => sel,
+            _ => return Vec::new(),
+        };
+
+        let start = if idx == sel.start.1 { sel.start.0 } else { 0 };
+        let end = if idx == sel.end.1 {
+            sel.end.0
+        } else {
+            self.text[idx].length() as usize - 1
+        };
+        if end <= self.cursor.col_offset as usize {
+            return Vec::new();
+        }
+        vec![(
+            self.screen_col(idx, start),
+            self.screen_col(idx, end),
+            Highlight::Selection,
+        )]
+    }
+
+    // Finds the matched brackets on a given row, if any, as ranges of screen columns.
+    fn find_brackets(&self, idx: usize) -> Vec<(usize, usize, Highlight)> {
+        let offset = self.cursor.col_offset as usize;
+        self.bracket_match
+            .iter()
+            .flatten()
+            .filter(|(x, y)| *y == idx && *x >= offset)
+            .map(|(x, _)| {
+                (
+                    self.screen_col(idx, *x),
+                    self.screen_col(idx, x + 1),
+                    Highlight::Bracket,
+                )
+            })
+            .collect()
+    }
+
+    // Finds the part of a given row that's inside a block selection, as a range of screen columns.
+    // A block with no width is drawn as a column of cursors, so that it can be seen.
+    fn find_block(&self, block: Block, idx: usize) -> Vec<(usize, usize, Highlight)> {
+        if idx < block.top || idx > block.bottom {
+            return Vec::new();
+        }
+        let row = &self.text[idx];
+        let tab_width = self.settings.tab_width;
+        let left = row.grapheme_at_col(block.left, tab_width);
+        let offset = self.cursor.col_offset as usize;
+        if block.is_empty() {
+            if left < offset || idx == self.cursor.position().1 {
+                return Vec::new();
+            }
+            let start = self.screen_col(idx, left);
+            return vec![(start, start + 1, Highlight::Cursor)];
+        }
+        let right = row.grapheme_at_col(block.right, tab_width);
+        if right <= offset {
+            return Vec::new();
+        }
+        vec![(
+            self.screen_col(idx, left),
+            self.screen_col(idx, right),
+            Highlight::Selection,
+        )]
+    }
+
+    // Finds the extra cursors on a given row that are on screen, as ranges of screen columns.
+    fn find_cursors(&self, idx: usize) -> Vec<(usize, usize, Highlight)> {
+        let offset = self.cursor.col_offset as usize;
+        let width = (self.cols - self.gutter_width()) as usize;
+        self.extra_cursors
+            .iter()
+            .filter(|(x, y)| *y == idx && *x >= offset)
+            .map(|(x, _)| self.screen_col(idx, *x))
+            .filter(|col| *col < width)
+            .map(|col| (col, col + 1, Highlight::Cursor))
+            .collect()
+    }
+
+    // Finds every search match on a given row, as ranges of screen columns.
+    // Matches can span whitespace, and can be partially scrolled off-screen to the left.
+    // The match that the cursor sits at the start of is the current match, and gets its own highlight.
+    fn find_highlights(&self, idx: usize) -> Vec<(usize, usize, Highlight)> {
+        let mut highlights = Vec::new();
+        let q = match &self.search_highlight {
+            Some(q) if !q.is_empty() => q,
+            _ => return highlights,
+        };
+
+        let raw = &self.text[idx].raw_text;
+        let offset = self.cursor.col_offset as usize;
+        let (cursor_x, cursor_y) = self.cursor.position();
+        for (byte_idx, _) in raw.match_indices(q.as_str()) {
+            let start = raw[..byte_idx].graphemes(true).count();
+            let end = raw[..byte_idx + q.len()].graphemes(true).count();
+            if end <= offset {
+                continue;
+            }
+            let kind = if idx == cursor_y && start == cursor_x {
+                Highlight::CurrentMatch
+            } else {
+                Highlight::Match
+            };
+            highlights.push((self.screen_col(idx, start), self.screen_col(idx, end), kind));
+        }
+        highlights
+    }
+
     // Sets the static status info of this file -- file name and # of lines in the file.
     fn set_status_info(&mut self) {
         let mut file: String;
-        if self.file_name.eq("") {
+        if self.file_name.is_empty() {
             file = "[Untitled]".to_string();
         } else if self.file_name.len() > 20 {
             file = self.file_name[..20].to_string();
@@ -184,10 +386,14 @@ impl RenderDriver {
                     .set_content(SAVE_SUCCESS_MSG.to_string());
                 self.set_status_info();
             }
+            StatusContent::SaveFailed(e) => {
+                let msg = format!("Couldn't save the file: {}", e);
+                self.status_message.set_content(msg);
+            }
             StatusContent::DirtyWarning(q) => {
                 let msg = format!(
                     "Warning! File has unsaved changes. Press Ctrl+Q {} more times to quit.",
-                    3 - q
+                    self.config.dirty_quit_count - q
                 );
                 self.status_message.set_content(msg);
                 self.set_status_info();
@@ -202,18 +408,211 @@ impl RenderDriver {
             }
             StatusContent::Find(q) => {
                 self.status_message.live_forever_for_now();
-                self.status_kind = StatusContent::Find(q.clone());
+                self.search_highlight = Some(q.clone());
                 let msg = format!("Search: {} (Use ESC to cancel)", q);
                 self.status_message.set_content(msg);
             }
+            StatusContent::FindResult(q, current, total, wrapped) => {
+                self.status_message.live_forever_for_now();
+                let progress = if total == 0 {
+                    " (no matches)".to_string()
+                } else if wrapped {
+                    format!(" (match {} of {}, wrapped)", current, total)
+                } else {
+                    format!(" (match {} of {})", current, total)
+                };
+                let msg = if q.is_empty() {
+                    format!("Search: {} (Use ESC to cancel)", q)
+                } else {
+                    format!("Search: {}{} (Use ESC to cancel)", q, progress)
+                };
+                self.search_highlight = Some(q);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::FindRepeat(current, total, wrapped) => {
+                let msg = if wrapped {
+                    format!("{} Match {} of {}.", SEARCH_WRAP_MSG, current, total)
+                } else {
+                    format!("Match {} of {}.", current, total)
+                };
+                self.status_message.set_content(msg);
+            }
+            StatusContent::FindMiss(q) => {
+                let msg = if q.is_empty() {
+                    NO_SEARCH_MSG.to_string()
+                } else {
+                    format!("Pattern not found: {}", q)
+                };
+                self.status_message.set_content(msg);
+            }
+            StatusContent::GoTo(target) => {
+                self.status_message.live_forever_for_now();
+                let msg = format!(
+                    "Go to line: {} (line, line:col, +N/-N or N%. Use ESC to cancel)",
+                    target
+                );
+                self.status_message.set_content(msg);
+            }
+            StatusContent::GoToLine(line, total) => {
+                let msg = format!("Line {} of {}.", line, total);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::GoToInvalid(target) => {
+                let msg = format!("Not a line number: {}", target);
+                self.status_message.set_content(msg);
+            }
             StatusContent::SaveAbort => self.status_message.set_content(SAVE_ABORT_MSG.to_string()),
+            StatusContent::ClipboardEmpty => self
+                .status_message
+                .set_content(CLIPBOARD_EMPTY_MSG.to_string()),
+            StatusContent::NoCommentSyntax => self
+                .status_message
+                .set_content(NO_COMMENT_SYNTAX_MSG.to_string()),
+            StatusContent::ClipboardError(e) => {
+                let msg = format!("Couldn't reach the system clipboard: {}", e);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::Command(text, candidates) => {
+                self.status_message.live_forever_for_now();
+                let msg = if candidates.is_empty() {
+                    format!("Command: {} (Use ESC to cancel)", text)
+                } else {
+                    let shown = candidates.iter().take(4).cloned().collect::<Vec<String>>();
+                    format!(
+                        "Command: {} [{}] (Tab to complete)",
+                        text,
+                        shown.join(" | ")
+                    )
+                };
+                self.status_message.set_content(msg);
+            }
+            StatusContent::CommandError(e) => self.status_message.set_content(e),
+            StatusContent::OptionSet(key, value) => {
+                let msg = format!("{} = {}", key, value);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::Replaced(n) => {
+                let msg = if n == 1 {
+                    "Replaced 1 occurrence.".to_string()
+                } else {
+                    format!("Replaced {} occurrences.", n)
+                };
+                self.status_message.set_content(msg);
+            }
+            StatusContent::LinesChanged(n) => {
+                let msg = if n == 1 {
+                    "1 line changed.".to_string()
+                } else {
+                    format!("{} lines changed.", n)
+                };
+                self.status_message.set_content(msg);
+            }
+            StatusContent::UndoEmpty(redo) => {
+                let msg = if redo {
+                    NOTHING_TO_REDO_MSG
+                } else {
+                    NOTHING_TO_UNDO_MSG
+                };
+                self.status_message.set_content(msg.to_string());
+            }
+            StatusContent::MacroRecord(register) => {
+                self.status_message.live_forever_for_now();
+                let msg = format!(
+                    "Record macro into register: {} (Enter for the last one. Use ESC to cancel)",
+                    register
+                );
+                self.status_message.set_content(msg);
+            }
+            StatusContent::MacroPlay(text) => {
+                self.status_message.live_forever_for_now();
+                let msg = format!(
+                    "Play macro: {} (register and count, like a 5. Use ESC to cancel)",
+                    text
+                );
+                self.status_message.set_content(msg);
+            }
+            StatusContent::Filter(cmd) => {
+                self.status_message.live_forever_for_now();
+                let msg = format!(
+                    "Filter through: {} (a shell command, like sort -k2. Use ESC to cancel)",
+                    cmd
+                );
+                self.status_message.set_content(msg);
+            }
+            StatusContent::ShellInsert(cmd) => {
+                self.status_message.live_forever_for_now();
+                let msg = format!(
+                    "Insert output of: {} (a shell command, like date. Use ESC to cancel)",
+                    cmd
+                );
+                self.status_message.set_content(msg);
+            }
+            StatusContent::ShellRunning(cmd) => {
+                self.status_message.live_forever_for_now();
+                let msg = format!("Running {}... (Use ESC to cancel)", cmd);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::ShellInserted(n, truncated) => {
+                let mut msg = if n == 1 {
+                    "Inserted 1 line.".to_string()
+                } else {
+                    format!("Inserted {} lines.", n)
+                };
+                if truncated {
+                    msg.push_str(" The output was too long, so it was cut off.");
+                }
+                self.status_message.set_content(msg);
+            }
+            StatusContent::ShellCancelled(cmd) => {
+                let msg = format!("Stopped {}.", cmd);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::MacroRecording(register) => {
+                let msg = format!("Recording macro {}...", register);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::MacroSaved(register, count) => {
+                let msg = format!("Saved macro {} ({} events).", register, count);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::MacroMissing(register) => {
+                let msg = format!("No macro recorded in register {}.", register);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::MacroStopped(reason) => {
+                let msg = format!("Macro stopped: {}", reason);
+                self.status_message.set_content(msg);
+            }
+            StatusContent::ConfigError(errors) => {
+                let msg = match errors.len() {
+                    0 => return,
+                    1 => errors[0].clone(),
+                    n => format!("{} (+{} more)", errors[0], n - 1),
+                };
+                self.status_message.set_content(msg);
+            }
             StatusContent::PromptAbort => {
                 self.status_message.immortal = false;
-                self.status_kind = StatusContent::Help;
+                self.search_highlight = None;
             }
         }
     }
 
+    // Sets the query whose matches should be highlighted on screen. None clears any highlighted matches.
+    pub fn highlight_search(&mut self, q: Option<String>) {
+        self.search_highlight = q;
+    }
+
+    // Sets the positions of any cursors besides the main one, so that they can be drawn.
+    pub fn set_extra_cursors(&mut self, cursors: Vec<(usize, usize)>) {
+        self.extra_cursors = cursors;
+    }
+
+    // Sets the pair of brackets to highlight on screen. None clears any highlighted brackets.
+    pub fn highlight_brackets(&mut self, pair: Option<[(usize, usize); 2]>) {
+        self.bracket_match = pair;
+    }
+
     // Updates this RenderDriver's current CursorState.
     pub fn update_cursor_state(&mut self, state: CursorState) {
         self.cursor = state;
@@ -225,19 +624,38 @@ impl RenderDriver {
         &mut self.text
     }
 
-    // Sets the text data of the RenderDriver.
+    // Sets the text data of the RenderDriver. This is a freshly opened file, so there are no unsaved changes.
     // At this point, the renderer should have everything that it needs to complete its initialization.
     pub fn set_text(&mut self, text: Vec<TextRow>) {
         self.text = text;
+        self.mod_status.clean();
         self.complete_init();
     }
 
+    // Puts back the rows changed by an undo (or redo), in place of the rows in a given range, and refreshes status info
+    // for the new document length. dirty is whether the restored text has unsaved changes.
+    pub fn restore_rows(&mut self, range: Range<usize>, rows: Vec<TextRow>, dirty: bool) {
+        self.text.splice(range, rows);
+        if dirty {
+            self.mod_status.redirty();
+        } else {
+            self.mod_status.clean();
+        }
+        self.set_status_info();
+    }
+
+    // Removes the row at a given index. Marks the editor dirty, and refreshes status info for the new document length.
     pub fn delete_row(&mut self, idx: usize) {
         self.text.remove(idx);
+        self.mod_status.redirty();
+        self.set_status_info();
     }
 
+    // Inserts a row at a given index. Marks the editor dirty, and refreshes status info for the new document length.
     pub fn insert_row(&mut self, idx: usize, text: TextRow) {
         self.text.insert(idx, text);
+        self.mod_status.redirty();
+        self.set_status_info();
     }
 
     // Update the text contained at a given row index.
@@ -258,31 +676,72 @@ impl RenderDriver {
         self.set_status_info();
     }
 
+    // Copies text to the terminal's clipboard with an OSC 52 escape sequence. The sequence is written out with the next tick.
+    // This works over SSH (and in tmux with set-clipboard enabled), since the terminal emulator on the host is the one handling it.
+    pub fn set_terminal_clipboard(&mut self, text: &str) {
+        write!(
+            self.buf,
+            "\x1b]52;c;{}\x07",
+            utils::base64_encode(text.as_bytes())
+        )
+        .expect(WRITE_ERR_MSG);
+    }
+
     // Whether or not the user is currently inputting force quits.
     pub fn is_quitting(&mut self) -> bool {
         self.mod_status.quit_count > 0
     }
 
-    // Saves the file name of the opened file.
+    // Saves the file name of the opened file, and picks the language used for syntax highlighting (and the settings) from it.
     // Could potentially be refactored out, but waiting to see if this is useful to keep.
     pub fn set_file_name(&mut self, name: &str) {
         self.file_name = name.to_string();
+        self.language = Language::detect(name);
+        self.settings = self.config.for_file(name);
+    }
+
+    // Changes one of the settings for the open file, from the command line. Returns the setting's full name.
+    pub fn set_setting(&mut self, key: &str, value: &str) -> Result<&'static str, String> {
+        config::set_from_text(&mut self.settings, key, value)
     }
 
-    // Exits the editor, clearing the entire window and resetting the cursor position.
+    // Returns the settings that apply to the open file.
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    // Set the vim mode label shown in the status bar. None hides it.
+    pub fn set_mode_label(&mut self, label: Option<String>) {
+        self.mode_label = label;
+    }
+
+    // Set the macro register label shown in the status bar. None hides it.
+    pub fn set_macro_label(&mut self, label: Option<String>) {
+        self.macro_label = label;
+    }
+
+    // Whether or not the open file has unsaved changes.
+    pub fn is_dirty(&self) -> bool {
+        self.mod_status.dirty
+    }
+
+    // Exits the editor, clearing the entire window, resetting the cursor position and turning off bracketed paste mode
+    // and mouse reporting.
     // If the editor is currently dirty, and the user has not force quit enough times, render a warning and do nothing.
     // Confirm shutdown only with sufficient force quits, or with a clean editor.
     pub fn exit(&mut self) -> bool {
-        if self.mod_status.dirty && self.mod_status.quit_count < 3 {
+        if self.mod_status.dirty && self.mod_status.quit_count < self.config.dirty_quit_count {
             self.update_status_message(StatusContent::DirtyWarning(self.mod_status.quit_count));
             self.mod_status.quit_count += 1;
             false
         } else {
             write!(
                 self.buf,
-                "{}{}",
+                "{}{}{}{}",
                 termion::cursor::Goto(1, 1),
-                termion::clear::All
+                termion::clear::All,
+                BRACKETED_PASTE_OFF,
+                MOUSE_OFF
             )
             .expect(WRITE_ERR_MSG);
             self.buf.flush().unwrap();
@@ -300,11 +759,12 @@ impl RenderDriver {
         )
         .expect(WRITE_ERR_MSG);
         self.set_screen();
+        let (x, row) = self.cursor.position();
         write!(
             self.buf,
             "{}{}",
             termion::cursor::Goto(
-                (self.cursor.cx + 1).try_into().unwrap(),
+                self.screen_col(row, x) as u16 + 1 + self.gutter_width(),
                 (self.cursor.cy + 1).try_into().unwrap()
             ),
             termion::cursor::Show
@@ -318,52 +778,157 @@ impl RenderDriver {
 
 // SYNTAX HIGHLIGHTING //
 
-// Determines the correct color for a token given its content.
-fn determine_color(token: &str) -> color::Fg<color::Rgb> {
+// Determines the correct color for a token given its content, the language's keywords and types, and the theme.
+fn determine_color(token: &str, lang: &Language, theme: &Theme) -> color::Fg<color::Rgb> {
     if token.parse::<f64>().is_ok() {
-        // digits are red
-        color::Fg(Rgb(255, 0, 0))
-    } else if C_KEYWORDS.contains(&token) {
-        // keywords are yellow
-        color::Fg(Rgb(255, 255, 0))
-    } else if C_TYPES.contains(&token) {
-        // types are green
-        color::Fg(Rgb(0, 255, 0))
+        color::Fg(theme.number)
+    } else if lang.keywords.contains(&token) {
+        color::Fg(theme.keyword)
+    } else if lang.types.contains(&token) {
+        color::Fg(theme.type_name)
     } else {
-        // rest is white
-        color::Fg(Rgb(255, 255, 255))
+        color::Fg(theme.text)
+    }
+}
+
+// Kinds of highlighting that can be layered on top of syntax highlighting.
+// Selection - Selected text, drawn in reverse video.
+// Match - Any search match on screen.
+// CurrentMatch - The search match that the cursor is currently on.
+// Bracket - The bracket under the cursor, and the bracket that matches it.
+// Cursor - A cursor besides the main one, drawn in reverse video.
+#[derive(Copy, Clone, PartialEq)]
+enum Highlight {
+    Selection,
+    Match,
+    CurrentMatch,
+    Bracket,
+    Cursor,
+}
+
+impl Highlight {
+    // Returns the escape codes that start a Highlight, using the theme's colors.
+    fn start(self, theme: &Theme) -> String {
+        match self {
+            Highlight::Selection | Highlight::Cursor => style::Invert.to_string(),
+            Highlight::Match => color::Bg(theme.search_match).to_string(),
+            Highlight::CurrentMatch => color::Bg(theme.current_match).to_string(),
+            Highlight::Bracket => color::Bg(theme.bracket).to_string(),
+        }
+    }
+}
+
+// SpanWriter. Writes the text of a single row to the buffer, one colored span at a time.
+// Keeps track of how many columns of the row have been written so far, so that any graphemes falling within a highlight
+// range can be drawn with that highlight's background -- regardless of how the row was split into tokens.
+// Also carries the theme, so that whatever writes to it knows which colors to use.
+struct SpanWriter<'a> {
+    buf: &'a mut BufWriter<RawTerminal<Stdout>>,
+    highlights: &'a [(usize, usize, Highlight)],
+    theme: &'a Theme,
+    col: usize,
+    current: Option<Highlight>,
+}
+
+impl<'a> SpanWriter<'a> {
+    fn new(
+        buf: &'a mut BufWriter<RawTerminal<Stdout>>,
+        highlights: &'a [(usize, usize, Highlight)],
+        theme: &'a Theme,
+    ) -> Self {
+        Self {
+            buf,
+            highlights,
+            theme,
+            col: 0,
+            current: None,
+        }
+    }
+
+    // Write a span of text in the given color. Switches background color whenever a highlight range starts or ends.
+    fn write(&mut self, fg: color::Fg<color::Rgb>, text: &str) {
+        write!(self.buf, "{}", fg).expect(WRITE_ERR_MSG);
+        for g in text.graphemes(true) {
+            let highlight = self
+                .highlights
+                .iter()
+                .find(|(start, end, _)| (*start..*end).contains(&self.col))
+                .map(|h| h.2);
+            if highlight != self.current {
+                if self.current.is_some() {
+                    self.reset();
+                }
+                if let Some(h) = highlight {
+                    write!(self.buf, "{}", h.start(self.theme)).expect(WRITE_ERR_MSG);
+                }
+                self.current = highlight;
+            }
+            write!(self.buf, "{}", g).expect(WRITE_ERR_MSG);
+            // tabs have already been expanded, so the tab width doesn't matter here
+            self.col += cell_width(g, self.col, 1);
+        }
+    }
+
+    // Extra cursors can sit past the last char of a row. Pads the row with blank cells out to the furthest one,
+    // so that it still gets drawn.
+    fn pad_cursors(&mut self) {
+        let end = self
+            .highlights
+            .iter()
+            .filter(|h| h.2 == Highlight::Cursor)
+            .map(|h| h.1)
+            .max();
+        if let Some(end) = end.filter(|end| *end > self.col) {
+            let pad = " ".repeat(end - self.col);
+            self.write(color::Fg(self.theme.text), &pad);
+        }
+    }
+
+    // Clears whatever highlight is currently being drawn.
+    fn reset(&mut self) {
+        write!(self.buf, "{}{}", style::NoInvert, color::Bg(color::Black)).expect(WRITE_ERR_MSG);
+    }
+}
+
+impl Drop for SpanWriter<'_> {
+    // Make sure a highlight never bleeds past the end of the row.
+    fn drop(&mut self) {
+        if self.current.is_some() {
+            self.reset();
+        }
     }
 }
 
 // Write a token using the correct color.
 // If a color is provided, use that color. Otherwise, determine the color based on the token.
-// If the token is in a string, color it magenta. If the token is in a comment, color it green.
+// If the token is in a string or a comment, use the theme's color for those instead.
 fn write_token(
-    buf: &mut BufWriter<RawTerminal<Stdout>>,
+    out: &mut SpanWriter,
     token: &str,
+    lang: &Language,
     fg: Option<color::Fg<color::Rgb>>,
     in_string: bool,
     in_comment: bool,
 ) {
     let mut color = match fg {
         Some(f) => f,
-        None => determine_color(token),
+        None => determine_color(token, lang, out.theme),
     };
 
     if in_string {
-        color = color::Fg(Rgb(255, 0, 255));
+        color = color::Fg(out.theme.string);
     } else if in_comment {
-        color = color::Fg(Rgb(0, 255, 0));
+        color = color::Fg(out.theme.comment);
     }
 
-    write!(buf, "{}{}", color, token).expect(WRITE_ERR_MSG);
+    out.write(color, token);
 }
 
 // Tokenizes a textrow, preserving whitespace as separate tokens.
 fn tokenize_preserve_whitespace(s: &str) -> Vec<String> {
     let mut tokens = Vec::new();
     let mut current_token = String::new();
-    let mut in_whitespace = s.chars().next().map_or(false, |c| c.is_whitespace());
+    let mut in_whitespace = s.chars().next().is_some_and(|c| c.is_whitespace());
 
     for c in s.chars() {
         if c.is_whitespace() {
@@ -390,34 +955,36 @@ fn tokenize_preserve_whitespace(s: &str) -> Vec<String> {
 }
 
 // Processes a list of tokens, determining the correct color for each token and writing it to the buffer.
+// Search highlighting is handled by the SpanWriter, so it layers on top of whatever color a token ends up with.
+// Comments are picked out using the language's comment markers.
 fn process_tokens(
-    buf: &mut BufWriter<RawTerminal<Stdout>>,
+    out: &mut SpanWriter,
     tokens: Vec<String>,
-    q: &str,
-    file_name: &str,
+    lang: &Language,
     multiline_comment: bool,
 ) -> bool {
-    // blue
-    let find_fg = color::Fg(Rgb(0, 0, 255));
     // in_string and in_comment are used to track whether we've been processing a string or a comment.
     // (Both strings and comments can span multiple tokens.)
     let mut in_string = false;
     let mut in_comment = multiline_comment;
+    let theme = out.theme;
 
-    // Always highlight find results, but only highlight syntax in C files.
-    let should_highlight =
-        file_name.ends_with(".c") || file_name.ends_with(".h") || file_name.ends_with(".cpp");
+    // Only highlight syntax in languages with keywords to highlight.
+    let should_highlight = !lang.keywords.is_empty();
 
-    // If the first token starts with '//', the line is a comment and should be colored cyan.
-    if !tokens.is_empty() && tokens[0].starts_with("//") {
+    // If the first token starts with a line comment marker, the line is a comment and gets the line comment color.
+    if !tokens.is_empty() && lang.line_comment.is_some_and(|m| tokens[0].starts_with(m)) {
         let comment = tokens.join("");
-        write!(buf, "{}", color::Fg(Rgb(0, 255, 255))).expect(WRITE_ERR_MSG);
-        write!(buf, "{} ", comment).expect(WRITE_ERR_MSG);
+        out.write(color::Fg(theme.line_comment), &format!("{} ", comment));
         return in_comment;
     }
 
-    // If the first token starts with '/*', assume a multi-line comment has begun.
-    if !tokens.is_empty() && tokens[0].starts_with("/*") {
+    // If the first token starts with a block comment marker, assume a multi-line comment has begun.
+    if !tokens.is_empty()
+        && lang
+            .block_comment
+            .is_some_and(|(start, _)| tokens[0].starts_with(start))
+    {
         in_comment = true;
     }
 
@@ -425,21 +992,7 @@ fn process_tokens(
         let magenta_start = token.find('"');
         let magenta_end = token.rfind('"');
 
-        // Blue highlighting for find query should override all other highlighting.
-        if !q.is_empty() && token.contains(q) {
-            let blue_start = token.find(q).unwrap();
-            let blue_end = blue_start + q.len();
-            let blue_token = &token[blue_start..blue_end];
-            let before_token = &token[..blue_start];
-            let after_token = &token[blue_end..];
-
-            let before_fg = determine_color(before_token);
-            let after_fg = determine_color(after_token);
-
-            write!(buf, "{}{}", before_fg, before_token).expect(WRITE_ERR_MSG);
-            write!(buf, "{}{}", find_fg, blue_token).expect(WRITE_ERR_MSG);
-            write!(buf, "{}{}", after_fg, after_token).expect(WRITE_ERR_MSG);
-        } else if should_highlight && magenta_start.is_some() && magenta_end.is_some() {
+        if should_highlight && magenta_start.is_some() && magenta_end.is_some() {
             if let (Some(start), Some(end)) = (magenta_start, magenta_end) {
                 // A quote appears in this token.
                 if start == end {
@@ -449,20 +1002,16 @@ fn process_tokens(
                         let magenta_token = &token[..end + 1];
                         let after_token = &token[end + 1..];
 
-                        write!(buf, "{}{}", color::Fg(Rgb(255, 0, 255)), magenta_token)
-                            .expect(WRITE_ERR_MSG);
-                        write!(buf, "{}{}", determine_color(after_token), after_token)
-                            .expect(WRITE_ERR_MSG);
+                        out.write(color::Fg(theme.string), magenta_token);
+                        out.write(determine_color(after_token, lang, theme), after_token);
                         in_string = false;
                     } else {
                         // Starting quote.
                         let before_token = &token[..start];
                         let magenta_token = &token[start..];
 
-                        write!(buf, "{}{}", determine_color(before_token), before_token)
-                            .expect(WRITE_ERR_MSG);
-                        write!(buf, "{}{}", color::Fg(Rgb(255, 0, 255)), magenta_token)
-                            .expect(WRITE_ERR_MSG);
+                        out.write(determine_color(before_token, lang, theme), before_token);
+                        out.write(color::Fg(theme.string), magenta_token);
                         in_string = true;
                     }
                 } else {
@@ -471,30 +1020,28 @@ fn process_tokens(
                     let magenta_token = &token[start..end + 1];
                     let after_token = &token[end + 1..];
 
-                    write!(buf, "{}{}", determine_color(before_token), before_token)
-                        .expect(WRITE_ERR_MSG);
-                    write!(buf, "{}{}", color::Fg(Rgb(255, 0, 255)), magenta_token)
-                        .expect(WRITE_ERR_MSG);
-                    write!(buf, "{}{}", determine_color(after_token), after_token)
-                        .expect(WRITE_ERR_MSG);
+                    out.write(determine_color(before_token, lang, theme), before_token);
+                    out.write(color::Fg(theme.string), magenta_token);
+                    out.write(determine_color(after_token, lang, theme), after_token);
                 }
             }
         } else {
-            // Syntax highlighting is only enabled for C files.
-            let fg = if should_highlight
-            {
+            // Syntax highlighting is only enabled for languages with keywords.
+            let fg = if should_highlight {
                 // Determine fg via determine_color later
                 None
             } else {
-                // white
-                Some(color::Fg(Rgb(255, 255, 255)))
+                Some(color::Fg(theme.text))
             };
 
-            write_token(buf, token, fg, in_string, in_comment);
+            write_token(out, token, lang, fg, in_string, in_comment);
         }
 
-        // If the token contains '*/', assume a multi-line comment has ended.
-        if token.contains("*/") {
+        // If the token contains the block comment's end marker, assume a multi-line comment has ended.
+        if lang
+            .block_comment
+            .is_some_and(|(_, end)| token.contains(end))
+        {
             in_comment = false;
         }
     }
@@ -507,15 +1054,19 @@ fn process_tokens(
 
 // Const strings for error messages and help messages.
 const WRITE_ERR_MSG: &str = "Failed to write to console.";
-const KEYBIND_HELP_MSG: &str = "HELP: Ctrl+Q - exit | Ctrl+S - save | Ctrl+F - find";
+
+const BRACKETED_PASTE_ON: &str = "\x1b[?2004h";
+const BRACKETED_PASTE_OFF: &str = "\x1b[?2004l";
+// Report button presses, drags with a button held down, and the wheel, using the SGR encoding.
+const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1006h";
+const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1002l\x1b[?1000l";
+const KEYBIND_HELP_MSG: &str =
+    "HELP: Ctrl+Q - exit | Ctrl+S - save | Ctrl+F - find | Ctrl+N/P - next/prev match";
 const SAVE_SUCCESS_MSG: &str = "Wrote file to disk.";
 const SAVE_ABORT_MSG: &str = "Save aborted.";
-
-// Const lists for syntax highlighting.
-const C_KEYWORDS: &[&str] = &[
-    "switch", "if", "while", "for", "break", "continue", "return", "else", "struct", "union",
-    "typedef", "static", "enum", "class", "case",
-];
-const C_TYPES: &[&str] = &[
-    "int", "long", "double", "float", "char", "unsigned", "signed", "void", "#include",
-];
+const SEARCH_WRAP_MSG: &str = "Search wrapped around.";
+const NO_SEARCH_MSG: &str = "No previous search.";
+const CLIPBOARD_EMPTY_MSG: &str = "Nothing to paste.";
+const NO_COMMENT_SYNTAX_MSG: &str = "This file type has no comment syntax.";
+const NOTHING_TO_UNDO_MSG: &str = "Nothing to undo.";
+const NOTHING_TO_REDO_MSG: &str = "Nothing to redo.";
@@ -0,0 +1,86 @@
+use termion::color::Rgb;
+
+// Theme. The colors that the editor draws text and highlights with.
+// text, number, keyword, type_name, string, comment, line_comment: foreground colors for syntax highlighting.
+// (comment is used for block comments, and line_comment for whole-line comments.)
+// line_number: foreground color of the line number gutter, when it's turned on.
+// selection isn't listed -- selected text is always drawn in reverse video.
+// search_match, current_match, bracket: background colors for search matches, the current match, and matched brackets.
+pub struct Theme {
+    pub name: &'static str,
+    pub text: Rgb,
+    pub number: Rgb,
+    pub keyword: Rgb,
+    pub type_name: Rgb,
+    pub string: Rgb,
+    pub comment: Rgb,
+    pub line_comment: Rgb,
+    pub line_number: Rgb,
+    pub search_match: Rgb,
+    pub current_match: Rgb,
+    pub bracket: Rgb,
+}
+
+impl Theme {
+    // Finds a built-in theme by name.
+    pub fn named(name: &str) -> Option<&'static Theme> {
+        THEMES.iter().find(|theme| theme.name == name)
+    }
+
+    // Returns the names of every built-in theme, for error messages.
+    pub fn names() -> Vec<&'static str> {
+        THEMES.iter().map(|theme| theme.name).collect()
+    }
+}
+
+// CONSTS //
+
+pub const DEFAULT_THEME: &Theme = &THEMES[0];
+
+const THEMES: &[Theme] = &[
+    // bright primary colors -- what gram has always looked like
+    Theme {
+        name: "default",
+        text: Rgb(255, 255, 255),
+        number: Rgb(255, 0, 0),
+        keyword: Rgb(255, 255, 0),
+        type_name: Rgb(0, 255, 0),
+        string: Rgb(255, 0, 255),
+        comment: Rgb(0, 255, 0),
+        line_comment: Rgb(0, 255, 255),
+        line_number: Rgb(128, 128, 128),
+        search_match: Rgb(0, 0, 255),
+        current_match: Rgb(255, 128, 0),
+        bracket: Rgb(96, 96, 96),
+    },
+    // the same scheme, toned down
+    Theme {
+        name: "muted",
+        text: Rgb(220, 220, 220),
+        number: Rgb(215, 135, 135),
+        keyword: Rgb(215, 195, 135),
+        type_name: Rgb(135, 195, 135),
+        string: Rgb(195, 150, 215),
+        comment: Rgb(120, 150, 120),
+        line_comment: Rgb(120, 160, 170),
+        line_number: Rgb(100, 100, 100),
+        search_match: Rgb(40, 60, 120),
+        current_match: Rgb(150, 90, 30),
+        bracket: Rgb(70, 70, 70),
+    },
+    // no syntax colors at all, only highlights
+    Theme {
+        name: "mono",
+        text: Rgb(255, 255, 255),
+        number: Rgb(255, 255, 255),
+        keyword: Rgb(255, 255, 255),
+        type_name: Rgb(255, 255, 255),
+        string: Rgb(255, 255, 255),
+        comment: Rgb(160, 160, 160),
+        line_comment: Rgb(160, 160, 160),
+        line_number: Rgb(128, 128, 128),
+        search_match: Rgb(80, 80, 80),
+        current_match: Rgb(140, 140, 140),
+        bracket: Rgb(60, 60, 60),
+    },
+];
@@ -1,40 +1,158 @@
 use crate::data::enums::{Direction, InputEvent};
+use crate::keymap::{KeyMatch, Keymap};
+use crate::vim::Vim;
+use std::collections::VecDeque;
 use std::io::stdin;
-use termion::event::Key;
-use termion::input::TermRead;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+use termion::event::{Event, Key, MouseButton, MouseEvent};
+use termion::input::TermReadEventsAndRaw;
 
 // Functions for reading and processing key inputs.
 
-// Read a key and return its Key value. If there is no keypress on this tick, return a Null keypress.
-fn read_key() -> Key {
-    if let Some(b) = stdin().keys().next() {
-        return b.unwrap();
+// Input. Reads events from stdin on a separate thread, which holds onto the same event iterator between reads.
+// termion reads up to two bytes at a time, and keeps the second one around if it belongs to the next event. A fresh iterator
+// on every read would throw that byte away -- which is how fast typing (or pasting) used to drop characters.
+// Reading on a thread means the editor doesn't have to block on stdin, so it can keep ticking (for autosave, and to clear
+// old status messages) while no keys are being pressed.
+// Keys are looked up in a Keymap. Keys that start a longer binding are held in pending until the sequence is complete.
+// If the keymap turns on modal editing, keys go through Vim first. A single vim command can run several events, so any
+// after the first are held in queued until the editor asks for them. While a prompt is open, Vim is skipped.
+pub struct Input {
+    events: Receiver<(Event, Vec<u8>)>,
+    keymap: Keymap,
+    pending: Vec<Key>,
+    vim: Option<Vim>,
+    queued: VecDeque<InputEvent>,
+    prompting: bool,
+}
+
+impl Input {
+    pub fn new(keymap: Keymap) -> Self {
+        let (tx, events) = mpsc::channel();
+        thread::spawn(move || {
+            for e in stdin().events_and_raw() {
+                if tx.send(e.unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+        let vim = keymap.is_modal().then(Vim::new);
+        Self {
+            events,
+            keymap,
+            pending: Vec::new(),
+            vim,
+            queued: VecDeque::new(),
+            prompting: false,
+        }
+    }
+
+    // Read an event, along with the raw bytes that made it up. If there is no event on this tick, return None.
+    fn read_event(&mut self) -> Option<(Event, Vec<u8>)> {
+        self.events.recv_timeout(TICK).ok()
+    }
+
+    // Read the rest of a bracketed paste, up until the paste end sequence.
+    // Raw bytes are collected instead of keys, so that the pasted text comes through exactly as it was sent.
+    // Terminals send line breaks in a paste as carriage returns, so those are normalized to newlines.
+    // This waits for the whole paste, rather than giving up at the end of a tick. If the end sequence never comes (the
+    // paste was cut short), whatever arrived is used once nothing more turns up for PASTE_TIMEOUT.
+    // Only the first PASTE_LIMIT bytes are kept. The rest is read and thrown away, so that it isn't typed in key by key.
+    fn read_paste(&mut self) -> String {
+        let mut bytes: Vec<u8> = Vec::new();
+        while let Ok((_, raw)) = self.events.recv_timeout(PASTE_TIMEOUT) {
+            if raw == PASTE_END {
+                break;
+            }
+            if bytes.len() < PASTE_LIMIT {
+                bytes.extend(raw);
+            }
+        }
+        bytes.truncate(PASTE_LIMIT);
+        String::from_utf8_lossy(&bytes)
+            .replace("\r\n", "\n")
+            .replace('\r', "\n")
+    }
+
+    // Process a read key into various InputEvents, using the keymap.
+    // A bracketed paste is read in its entirety, and comes through as a single Paste event.
+    // A character that isn't bound to anything is written into the text. Any other unbound key (or sequence of keys) does nothing.
+    pub fn proc_key(&mut self) -> Option<InputEvent> {
+        let k = match self.read_event()? {
+            (Event::Key(k), _) => k,
+            (Event::Mouse(m), _) => return proc_mouse(m),
+            (Event::Unsupported(raw), _) if raw == PASTE_START => {
+                return Some(InputEvent::Paste(self.read_paste()))
+            }
+            _ => return None,
+        };
+
+        let modal = !self.prompting && self.pending.is_empty();
+        if let Some(vim) = self.vim.as_mut().filter(|_| modal) {
+            if let Some(events) = vim.handle_key(k) {
+                self.queued.extend(events);
+                return self.queued.pop_front();
+            }
+        }
+
+        self.pending.push(k);
+        let evt = match self.keymap.lookup(&self.pending) {
+            KeyMatch::Pending => None,
+            KeyMatch::Command(evt) => {
+                self.pending.clear();
+                Some(evt)
+            }
+            KeyMatch::Unbound => match std::mem::take(&mut self.pending)[..] {
+                [Key::Char(c)] => Some(InputEvent::Write(c)),
+                _ => None,
+            },
+        };
+        if let (Some(vim), Some(evt)) = (self.vim.as_mut().filter(|_| modal), &evt) {
+            vim.record(evt);
+        }
+        evt
+    }
+
+    // Takes the next event left over from a vim command that runs several, if there is one.
+    pub fn next_queued(&mut self) -> Option<InputEvent> {
+        self.queued.pop_front()
+    }
+
+    // Lets the modal layer know whether a prompt is open. Keys typed into a prompt always go straight through the keymap.
+    pub fn set_prompting(&mut self, prompting: bool) {
+        self.prompting = prompting;
+    }
+
+    // Returns the label for the current vim mode, or None if modal editing is off.
+    pub fn mode_label(&self) -> Option<String> {
+        self.vim.as_ref().map(|vim| vim.mode_label())
     }
-    Key::Null
 }
 
-// Process a read key into various InputEvents.
-// If the key does not match a known InputEvent, nothing happens.
-pub fn proc_key() -> Option<InputEvent> {
-    let k = read_key();
-
-    match k {
-        Key::Ctrl('q') => Some(InputEvent::Quit),
-        Key::Ctrl('s') => Some(InputEvent::Save),
-        Key::Ctrl('h') => Some(InputEvent::Delete(Direction::Left)),
-        Key::Ctrl('f') => Some(InputEvent::Find),
-        Key::Esc => Some(InputEvent::Cancel),
-        Key::Up => Some(InputEvent::Move(Direction::Up)),
-        Key::Left => Some(InputEvent::Move(Direction::Left)),
-        Key::Down => Some(InputEvent::Move(Direction::Down)),
-        Key::Right => Some(InputEvent::Move(Direction::Right)),
-        Key::PageUp => Some(InputEvent::Page(Direction::Up)),
-        Key::PageDown => Some(InputEvent::Page(Direction::Down)),
-        Key::Home => Some(InputEvent::Page(Direction::Left)),
-        Key::End => Some(InputEvent::Page(Direction::Right)),
-        Key::Backspace => Some(InputEvent::Delete(Direction::Left)),
-        Key::Delete => Some(InputEvent::Delete(Direction::Right)),
-        Key::Char(char) => Some(InputEvent::Write(char)),
+// Process a mouse event into an InputEvent. Only the left button and the wheel do anything.
+fn proc_mouse(m: MouseEvent) -> Option<InputEvent> {
+    match m {
+        MouseEvent::Press(MouseButton::Left, x, y) => Some(InputEvent::Click(x, y)),
+        MouseEvent::Press(MouseButton::WheelUp, _, _) => Some(InputEvent::Scroll(Direction::Up)),
+        MouseEvent::Press(MouseButton::WheelDown, _, _) => {
+            Some(InputEvent::Scroll(Direction::Down))
+        }
+        MouseEvent::Hold(x, y) => Some(InputEvent::Drag(x, y)),
         _ => None,
     }
 }
+
+// How long to wait for an event before giving the editor a tick without one.
+const TICK: Duration = Duration::from_millis(250);
+
+// Escape sequences that the terminal wraps around pasted text, once bracketed paste mode is on.
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+// How long a paste can go quiet before it's assumed that its end sequence got lost.
+const PASTE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How much of a single paste is kept, in bytes.
+const PASTE_LIMIT: usize = 16 * 1024 * 1024;
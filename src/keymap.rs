@@ -0,0 +1,460 @@
+use crate::data::enums::{Direction, InputEvent};
+use crate::utils;
+use std::{collections::HashMap, fs};
+use termion::event::Key;
+
+// Functions for mapping keys to editor commands.
+
+// Keymap. Maps sequences of keys to the names of editor commands.
+// Most bindings are a single key, but a sequence of keys (like "ctrl-x ctrl-s") can be bound too.
+// Starts out with DEFAULT_BINDINGS, which the user's keymap file can add to or override.
+// The keymap file can also pick a preset. The "emacs" preset swaps in EMACS_BINDINGS on top of the defaults. The "vim"
+// preset turns on modal editing, where the bindings are only used in insert mode (and for keys that normal mode doesn't handle).
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, String>,
+    modal: bool,
+}
+
+// Key Match. The result of looking up a sequence of keys in a Keymap.
+// Command - The keys are bound to a command. Contains the command's InputEvent.
+// Pending - The keys are the start of at least one longer binding, so more keys are needed.
+// Unbound - The keys aren't bound to anything.
+pub enum KeyMatch {
+    Command(InputEvent),
+    Pending,
+    Unbound,
+}
+
+impl Keymap {
+    // Builds a Keymap out of the default bindings alone.
+    pub fn new() -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+            modal: false,
+        };
+        for (keys, command) in DEFAULT_BINDINGS {
+            keymap
+                .bind(keys, command)
+                .expect("Invalid default binding.");
+        }
+        keymap
+    }
+
+    // Builds a Keymap out of the default bindings, plus anything in the user's keymap file (if there is one).
+    // Also returns a list of problems with the keymap file -- unreadable TOML, unknown keys or command names, bindings that
+    // can never be reached, and the same keys bound twice -- so that they can be reported at startup. Bad bindings are
+    // skipped. Rebinding a key that the defaults or the preset already use is deliberate, so it isn't a problem.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut keymap = Self::new();
+        let mut errors = Vec::new();
+        let path = match utils::config_path(KEYMAP_FILE) {
+            Some(path) if path.exists() => path,
+            _ => return (keymap, errors),
+        };
+
+        let table = match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| {
+                s.parse::<toml::Table>()
+                    .map_err(|e| e.message().to_string())
+            }) {
+            Ok(table) => table,
+            Err(e) => {
+                errors.push(format!("{}: {}", KEYMAP_FILE, e));
+                return (keymap, errors);
+            }
+        };
+
+        // the preset comes first, so that bindings can be layered on top of it
+        match table.get("preset") {
+            None => (),
+            Some(toml::Value::String(preset)) if preset == "default" => (),
+            Some(toml::Value::String(preset)) if preset == "vim" => keymap.modal = true,
+            Some(toml::Value::String(preset)) if preset == "emacs" => {
+                for (keys, command) in EMACS_BINDINGS {
+                    keymap.bind(keys, command).expect("Invalid emacs binding.");
+                }
+            }
+            Some(_) => errors.push(format!(
+                "{}: preset should be one of {}",
+                KEYMAP_FILE,
+                PRESETS.join(", ")
+            )),
+        }
+
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("preset", _) => (),
+                ("bindings", toml::Value::Table(bindings)) => {
+                    // the sequences bound so far, and how they were written, so that one bound twice can be pointed out
+                    let mut bound: HashMap<Vec<Key>, &str> = HashMap::new();
+                    for (keys, command) in bindings {
+                        let command = match command.as_str() {
+                            Some(command) => command,
+                            None => {
+                                errors.push(format!(
+                                    "{}: the command for \"{}\" should be a string",
+                                    KEYMAP_FILE, keys
+                                ));
+                                continue;
+                            }
+                        };
+                        if let Err(e) = keymap.bind(keys, command) {
+                            errors.push(format!("{}: {}", KEYMAP_FILE, e));
+                            continue;
+                        }
+                        let sequence = parse_sequence(keys).expect("Sequence was already bound.");
+                        if let Some(first) = bound.insert(sequence, keys) {
+                            errors.push(format!(
+                                "{}: \"{}\" and \"{}\" are the same keys, so only \"{}\" ({}) is used",
+                                KEYMAP_FILE, first, keys, keys, command
+                            ));
+                        }
+                    }
+                }
+                _ => errors.push(format!("{}: unknown setting \"{}\"", KEYMAP_FILE, key)),
+            }
+        }
+        errors.extend(
+            keymap
+                .conflicts()
+                .into_iter()
+                .map(|e| format!("{}: {}", KEYMAP_FILE, e)),
+        );
+        (keymap, errors)
+    }
+
+    // Whether the keymap's preset turns on vim-style modal editing.
+    pub fn is_modal(&self) -> bool {
+        self.modal
+    }
+
+    // Binds a sequence of keys (written like "ctrl-s" or "ctrl-x ctrl-s") to a named command.
+    // Binding a sequence to "none" removes any binding it had. Binding one that's already bound replaces its command.
+    fn bind(&mut self, keys: &str, command: &str) -> Result<(), String> {
+        let sequence = parse_sequence(keys)?;
+        if command == "none" {
+            self.bindings.remove(&sequence);
+        } else if command_event(command).is_some() {
+            self.bindings.insert(sequence, command.to_string());
+        } else {
+            return Err(format!("unknown command \"{}\" for \"{}\"", command, keys));
+        }
+        Ok(())
+    }
+
+    // Finds any bindings that can't be reached, because a shorter binding is the start of them.
+    // (The shorter binding always runs first.)
+    fn conflicts(&self) -> Vec<String> {
+        let mut conflicts: Vec<String> = self
+            .bindings
+            .keys()
+            .filter_map(|seq| {
+                let shadowed = self
+                    .bindings
+                    .keys()
+                    .find(|other| other.len() > seq.len() && other.starts_with(seq))?;
+                Some(format!(
+                    "\"{}\" ({}) hides the longer binding \"{}\"",
+                    describe_sequence(seq),
+                    self.bindings[seq],
+                    describe_sequence(shadowed)
+                ))
+            })
+            .collect();
+        conflicts.sort();
+        conflicts
+    }
+
+    // Looks up a sequence of keys, which might be the start of a longer binding.
+    pub fn lookup(&self, keys: &[Key]) -> KeyMatch {
+        if let Some(command) = self.bindings.get(keys) {
+            return match command_event(command) {
+                Some(evt) => KeyMatch::Command(evt),
+                None => KeyMatch::Unbound,
+            };
+        }
+        if self
+            .bindings
+            .keys()
+            .any(|seq| seq.len() > keys.len() && seq.starts_with(keys))
+        {
+            KeyMatch::Pending
+        } else {
+            KeyMatch::Unbound
+        }
+    }
+}
+
+// Returns the InputEvent for a named command, or None if there's no command with that name.
+pub fn command_event(name: &str) -> Option<InputEvent> {
+    ACTIONS
+        .iter()
+        .find(|(action, _)| *action == name)
+        .map(|(_, evt)| evt.clone())
+}
+
+// Returns the names of every command that can be bound to a key.
+pub fn command_names() -> impl Iterator<Item = &'static str> {
+    ACTIONS.iter().map(|(name, _)| *name)
+}
+
+// Parses a space-separated sequence of keys, like "ctrl-x ctrl-s".
+fn parse_sequence(s: &str) -> Result<Vec<Key>, String> {
+    let keys: Vec<Key> = s
+        .split_whitespace()
+        .map(|k| parse_key(k).ok_or_else(|| format!("unknown key \"{}\"", k)))
+        .collect::<Result<_, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key binding".to_string());
+    }
+    Ok(keys)
+}
+
+// Parses a single key, like "a", "enter", "ctrl-s", "alt-backspace" or "shift-up".
+// Modifiers and key names aren't case sensitive, but a single character is taken as it is (so "A" is a capital A).
+fn parse_key(s: &str) -> Option<Key> {
+    let (modifier, name) = match s.split_once('-') {
+        Some((m, name)) if !name.is_empty() => (m.to_lowercase(), name),
+        _ => (String::new(), s),
+    };
+    let lower = name.to_lowercase();
+    let mut chars = name.chars();
+    let single = match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    };
+
+    let key = match (modifier.as_str(), lower.as_str()) {
+        ("", "up") => Key::Up,
+        ("", "down") => Key::Down,
+        ("", "left") => Key::Left,
+        ("", "right") => Key::Right,
+        ("", "home") => Key::Home,
+        ("", "end") => Key::End,
+        ("", "pageup") => Key::PageUp,
+        ("", "pagedown") => Key::PageDown,
+        ("", "backspace") => Key::Backspace,
+        ("", "delete") => Key::Delete,
+        ("", "insert") => Key::Insert,
+        ("", "esc") => Key::Esc,
+        ("", "tab") => Key::Char('\t'),
+        ("", "enter") => Key::Char('\n'),
+        ("", "space") => Key::Char(' '),
+        ("", f) if f.starts_with('f') && f[1..].parse::<u8>().is_ok() => {
+            Key::F(f[1..].parse().unwrap())
+        }
+        ("shift", "up") => Key::ShiftUp,
+        ("shift", "down") => Key::ShiftDown,
+        ("shift", "left") => Key::ShiftLeft,
+        ("shift", "right") => Key::ShiftRight,
+        ("shift", "tab") => Key::BackTab,
+        ("ctrl", "up") => Key::CtrlUp,
+        ("ctrl", "down") => Key::CtrlDown,
+        ("ctrl", "left") => Key::CtrlLeft,
+        ("ctrl", "right") => Key::CtrlRight,
+        ("ctrl", "home") => Key::CtrlHome,
+        ("ctrl", "end") => Key::CtrlEnd,
+        ("ctrl", "space") => Key::Null,
+        // terminals send Ctrl+/ as the same byte as Ctrl+7
+        ("ctrl", "/") => Key::Ctrl('7'),
+        ("alt", "up") => Key::AltUp,
+        ("alt", "down") => Key::AltDown,
+        ("alt", "left") => Key::AltLeft,
+        ("alt", "right") => Key::AltRight,
+        ("alt", "backspace") => Key::Alt('\x7f'),
+        ("alt", "space") => Key::Alt(' '),
+        ("ctrl", _) => Key::Ctrl(single?.to_ascii_lowercase()),
+        ("alt", _) => Key::Alt(single?),
+        ("", _) => Key::Char(single?),
+        _ => return None,
+    };
+    Some(key)
+}
+
+// Writes a sequence of keys back out in the same notation that parse_sequence reads.
+fn describe_sequence(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|k| match k {
+            Key::Ctrl('7') => "ctrl-/".to_string(),
+            Key::Ctrl(c) => format!("ctrl-{}", c),
+            Key::Alt('\x7f') => "alt-backspace".to_string(),
+            Key::Alt(c) => format!("alt-{}", c),
+            Key::Char('\t') => "tab".to_string(),
+            Key::Char('\n') => "enter".to_string(),
+            Key::Char(' ') => "space".to_string(),
+            Key::Char(c) => c.to_string(),
+            Key::Null => "ctrl-space".to_string(),
+            Key::BackTab => "shift-tab".to_string(),
+            Key::F(n) => format!("f{}", n),
+            other => format!("{:?}", other).to_lowercase(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// CONSTS //
+
+const KEYMAP_FILE: &str = "keymap.toml";
+
+// The presets that the keymap file can choose from.
+const PRESETS: &[&str] = &["default", "emacs", "vim"];
+
+// Every command that can be bound to a key, as (command name, InputEvent).
+const ACTIONS: &[(&str, InputEvent)] = &[
+    ("quit", InputEvent::Quit),
+    ("save", InputEvent::Save),
+    ("find", InputEvent::Find),
+    ("find-next", InputEvent::FindNext),
+    ("find-prev", InputEvent::FindPrev),
+    ("search-forward", InputEvent::Search(true)),
+    ("search-backward", InputEvent::Search(false)),
+    ("goto-line", InputEvent::GoTo),
+    ("command-line", InputEvent::CommandLine),
+    ("cancel", InputEvent::Cancel),
+    ("move-up", InputEvent::Move(Direction::Up)),
+    ("move-down", InputEvent::Move(Direction::Down)),
+    ("move-left", InputEvent::Move(Direction::Left)),
+    ("move-right", InputEvent::Move(Direction::Right)),
+    ("move-word-left", InputEvent::MoveWord(Direction::Left)),
+    ("move-word-right", InputEvent::MoveWord(Direction::Right)),
+    ("move-next-word", InputEvent::NextWord),
+    ("page-up", InputEvent::Page(Direction::Up)),
+    ("page-down", InputEvent::Page(Direction::Down)),
+    ("line-start", InputEvent::Page(Direction::Left)),
+    ("line-end", InputEvent::Page(Direction::Right)),
+    ("select-up", InputEvent::Select(Direction::Up)),
+    ("select-down", InputEvent::Select(Direction::Down)),
+    ("select-left", InputEvent::Select(Direction::Left)),
+    ("select-right", InputEvent::Select(Direction::Right)),
+    ("set-mark", InputEvent::SetMark),
+    ("delete-left", InputEvent::Delete(Direction::Left)),
+    ("delete-right", InputEvent::Delete(Direction::Right)),
+    ("delete-char-left", InputEvent::DeleteChar(Direction::Left)),
+    (
+        "delete-char-right",
+        InputEvent::DeleteChar(Direction::Right),
+    ),
+    ("delete-word-left", InputEvent::DeleteWord(Direction::Left)),
+    (
+        "delete-word-right",
+        InputEvent::DeleteWord(Direction::Right),
+    ),
+    ("dedent", InputEvent::Dedent),
+    ("copy", InputEvent::Copy),
+    ("copy-back", InputEvent::CopyBack),
+    ("cut", InputEvent::Cut),
+    ("kill-line", InputEvent::KillLine),
+    ("paste", InputEvent::Yank),
+    ("paste-after", InputEvent::YankAfter),
+    ("paste-cycle", InputEvent::YankCycle),
+    ("jump-bracket", InputEvent::JumpBracket),
+    ("toggle-comment", InputEvent::ToggleComment),
+    ("undo", InputEvent::Undo),
+    ("redo", InputEvent::Redo),
+    ("macro-record", InputEvent::MacroRecord),
+    ("macro-play", InputEvent::MacroPlay),
+    ("duplicate-lines", InputEvent::DuplicateLines),
+    ("move-lines-up", InputEvent::MoveLines(Direction::Up)),
+    ("move-lines-down", InputEvent::MoveLines(Direction::Down)),
+    ("delete-lines", InputEvent::DeleteLines),
+    ("join-lines", InputEvent::JoinLines),
+    ("block-select", InputEvent::BlockSelect),
+    ("filter", InputEvent::Filter),
+    ("shell-insert", InputEvent::ShellInsert),
+    ("add-cursor-above", InputEvent::AddCursor(Direction::Up)),
+    ("add-cursor-below", InputEvent::AddCursor(Direction::Down)),
+    ("add-cursor-next", InputEvent::AddCursorNext),
+    ("add-cursors-at-matches", InputEvent::AddCursorMatches),
+];
+
+// The bindings gram starts out with, as (key sequence, command name).
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("ctrl-q", "quit"),
+    ("ctrl-s", "save"),
+    ("ctrl-f", "find"),
+    ("ctrl-n", "find-next"),
+    ("ctrl-p", "find-prev"),
+    ("ctrl-g", "goto-line"),
+    ("ctrl-e", "command-line"),
+    ("esc", "cancel"),
+    ("up", "move-up"),
+    ("down", "move-down"),
+    ("left", "move-left"),
+    ("right", "move-right"),
+    ("ctrl-left", "move-word-left"),
+    ("ctrl-right", "move-word-right"),
+    ("alt-left", "move-word-left"),
+    ("alt-right", "move-word-right"),
+    ("pageup", "page-up"),
+    ("pagedown", "page-down"),
+    ("home", "line-start"),
+    ("end", "line-end"),
+    ("shift-up", "select-up"),
+    ("shift-down", "select-down"),
+    ("shift-left", "select-left"),
+    ("shift-right", "select-right"),
+    ("ctrl-space", "set-mark"),
+    ("backspace", "delete-left"),
+    ("ctrl-h", "delete-left"),
+    ("delete", "delete-right"),
+    ("alt-backspace", "delete-word-left"),
+    ("ctrl-w", "delete-word-left"),
+    ("alt-d", "delete-word-right"),
+    ("shift-tab", "dedent"),
+    ("ctrl-c", "copy"),
+    ("ctrl-x", "cut"),
+    ("ctrl-v", "paste"),
+    ("alt-y", "paste-cycle"),
+    ("ctrl-b", "jump-bracket"),
+    ("ctrl-/", "toggle-comment"),
+    ("alt-;", "toggle-comment"),
+    ("ctrl-z", "undo"),
+    ("ctrl-y", "redo"),
+    ("f3", "macro-record"),
+    ("f4", "macro-play"),
+    ("ctrl-l", "duplicate-lines"),
+    ("alt-up", "move-lines-up"),
+    ("alt-down", "move-lines-down"),
+    ("ctrl-k", "delete-lines"),
+    ("alt-j", "join-lines"),
+    ("alt-b", "block-select"),
+    ("alt-|", "filter"),
+    ("alt-!", "shell-insert"),
+    ("ctrl-up", "add-cursor-above"),
+    ("ctrl-down", "add-cursor-below"),
+    ("ctrl-d", "add-cursor-next"),
+    ("alt-l", "add-cursors-at-matches"),
+];
+
+// The emacs preset's bindings, as (key sequence, command name). These go on top of DEFAULT_BINDINGS, so anything
+// not mentioned here keeps its default. Ctrl+X is unbound, since it starts the C-x chords.
+const EMACS_BINDINGS: &[(&str, &str)] = &[
+    ("ctrl-a", "line-start"),
+    ("ctrl-e", "line-end"),
+    ("ctrl-n", "move-down"),
+    ("ctrl-p", "move-up"),
+    ("ctrl-f", "move-right"),
+    ("ctrl-b", "move-left"),
+    ("alt-f", "move-word-right"),
+    ("alt-b", "move-word-left"),
+    ("alt-<", "page-up"),
+    ("alt->", "page-down"),
+    ("ctrl-d", "delete-right"),
+    ("ctrl-k", "kill-line"),
+    ("ctrl-w", "cut"),
+    ("alt-w", "copy"),
+    ("ctrl-y", "paste"),
+    ("ctrl-s", "search-forward"),
+    ("ctrl-r", "search-backward"),
+    ("ctrl-g", "cancel"),
+    ("alt-g g", "goto-line"),
+    ("alt-x", "command-line"),
+    ("ctrl-x", "none"),
+    ("ctrl-x ctrl-s", "save"),
+    ("ctrl-x ctrl-c", "quit"),
+    ("ctrl-x u", "undo"),
+    ("ctrl-x (", "macro-record"),
+    ("ctrl-x )", "macro-record"),
+    ("ctrl-x e", "macro-play"),
+];
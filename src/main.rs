@@ -2,13 +2,19 @@ use std::env::args;
 use std::io::Error;
 
 mod backend;
+mod config;
 mod data;
 mod gfx;
 mod input;
+mod keymap;
 mod utils;
+mod vim;
 
+use crate::config::Config;
 use crate::data::enums::InputEvent;
 use crate::gfx::controller::RenderController;
+use crate::input::Input;
+use crate::keymap::Keymap;
 
 // Driver function.
 fn main() {
@@ -16,9 +22,12 @@ fn main() {
     editor.tick();
 }
 
-// Represents an initialized editor. Only contains a controller.
+// Represents an initialized editor. Contains a controller, and the input reader that feeds it.
+// Any problems found while loading config files are held onto until the editor has started, so they can be shown.
 pub struct Gram {
     ctrl: RenderController,
+    input: Input,
+    config_errors: Vec<String>,
 }
 
 impl Default for Gram {
@@ -29,14 +38,21 @@ impl Default for Gram {
 
 impl Gram {
     pub fn new() -> Self {
+        let (config, mut config_errors) = Config::load();
+        let (keymap, keymap_errors) = Keymap::load();
+        config_errors.extend(keymap_errors);
         Self {
-            ctrl: RenderController::new(),
+            ctrl: RenderController::new(config),
+            input: Input::new(keymap),
+            config_errors,
         }
     }
 
     // Main function.
     // Read file contents if a path is provided.
-    // Until the program exits, enter a loop of ticking the screen and processing key inputs. Any key events will be passed to the controller.
+    // Until the program exits, enter a loop of ticking the screen and processing key inputs. Any key events will be passed to the controller,
+    // through handle_event.
+    // After each tick, the controller gets a chance to autosave, and to insert the output of a shell command that's finished.
     pub fn tick(&mut self) {
         let mut err: Result<(), Error>;
         let mut evt: Option<InputEvent>;
@@ -46,33 +62,104 @@ impl Gram {
             None => self.ctrl.finish_early(),
             Some(str) => self.ctrl.read_file(&str),
         };
+        self.ctrl
+            .report_config_errors(std::mem::take(&mut self.config_errors));
 
-        loop {
+        'main: loop {
             err = self.ctrl.tick_screen();
-            evt = input::proc_key();
+            evt = self.input.proc_key();
+            self.ctrl.autosave();
+            self.ctrl.check_shell_job();
 
             if err.is_err() {
                 break;
             }
 
-            match evt {
-                Some(InputEvent::Quit) => {
-                    let shutdown = self.ctrl.exit();
-                    if shutdown {
-                        break;
-                    }
+            // Commands run from the command line can queue up another event, which is handled straight after.
+            // So can vim commands, which often run several events in a row, and macros being replayed.
+            let mut next = evt;
+            while let Some(evt) = next {
+                if self.handle_event(evt) {
+                    break 'main;
                 }
-                Some(InputEvent::Save) => self.ctrl.write_file(),
-                Some(InputEvent::Move(d)) => self.ctrl.queue_move(d),
-                Some(InputEvent::Page(d)) => self.ctrl.queue_scroll(d),
-                Some(InputEvent::Write(c)) => self.ctrl.queue_write(c),
-                Some(InputEvent::Delete(d)) => self.ctrl.queue_delete(d),
-                Some(InputEvent::Cancel) => self.ctrl.exit_prompt(),
-                Some(InputEvent::Find) => self.ctrl.start_prompt(InputEvent::Find),
-                None => (),
+                next = self
+                    .ctrl
+                    .take_queued_event()
+                    .or_else(|| self.input.next_queued());
             }
+            self.input.set_prompting(self.ctrl.is_prompting());
+            self.ctrl.set_mode_label(self.input.mode_label());
         }
 
         self.ctrl.exit();
     }
+
+    // Passes an event on to the controller. Returns whether the editor should shut down.
+    // Typing, deleting and moving happen at every cursor, when there's more than one.
+    fn handle_event(&mut self, evt: InputEvent) -> bool {
+        self.ctrl.before_event(&evt);
+        if self.ctrl.spans_cursors(&evt) {
+            self.ctrl.at_every_cursor(|ctrl| {
+                dispatch(ctrl, evt.clone());
+            });
+            return false;
+        }
+        dispatch(&mut self.ctrl, evt)
+    }
+}
+
+// Hands an event to the controller method that handles it. Returns whether the editor should shut down.
+fn dispatch(ctrl: &mut RenderController, evt: InputEvent) -> bool {
+    match evt {
+        InputEvent::Quit => return ctrl.exit(),
+        InputEvent::Save => {
+            ctrl.write_file();
+        }
+        InputEvent::Move(d) => ctrl.queue_move(d),
+        InputEvent::MoveWord(d) => ctrl.queue_move_word(d),
+        InputEvent::NextWord => ctrl.queue_next_word(),
+        InputEvent::Page(d) => ctrl.queue_scroll(d),
+        InputEvent::Write(c) => ctrl.queue_write(c),
+        InputEvent::Paste(s) => ctrl.queue_paste_text(&s),
+        InputEvent::Click(x, y) => ctrl.queue_click(x, y, false),
+        InputEvent::Drag(x, y) => ctrl.queue_click(x, y, true),
+        InputEvent::Scroll(d) => ctrl.queue_wheel(d),
+        InputEvent::Delete(d) => ctrl.queue_delete(d),
+        InputEvent::DeleteChar(d) => ctrl.queue_delete_char(d),
+        InputEvent::DeleteWord(d) => ctrl.queue_delete_word(d),
+        InputEvent::Cancel => ctrl.exit_prompt(),
+        InputEvent::Find => ctrl.start_prompt(InputEvent::Find),
+        InputEvent::GoTo => ctrl.start_prompt(InputEvent::GoTo),
+        InputEvent::FindNext => ctrl.queue_find_repeat(true),
+        InputEvent::FindPrev => ctrl.queue_find_repeat(false),
+        InputEvent::Search(forward) => ctrl.queue_search(forward),
+        InputEvent::Select(d) => ctrl.queue_select(d),
+        InputEvent::SetMark => ctrl.queue_mark(),
+        InputEvent::Dedent => ctrl.queue_indent(true),
+        InputEvent::Copy => ctrl.queue_copy(false),
+        InputEvent::CopyBack => ctrl.queue_copy(true),
+        InputEvent::Cut => ctrl.queue_cut(),
+        InputEvent::KillLine => ctrl.queue_kill_line(),
+        InputEvent::Yank => ctrl.queue_paste(false),
+        InputEvent::YankAfter => ctrl.queue_paste(true),
+        InputEvent::YankCycle => ctrl.queue_paste_cycle(),
+        InputEvent::JumpBracket => ctrl.queue_jump_bracket(),
+        InputEvent::ToggleComment => ctrl.queue_comment(),
+        InputEvent::Undo => ctrl.queue_undo(false),
+        InputEvent::Redo => ctrl.queue_undo(true),
+        InputEvent::MacroRecord => ctrl.queue_macro_record(),
+        InputEvent::MacroPlay => ctrl.start_prompt(InputEvent::MacroPlay),
+        InputEvent::DuplicateLines => ctrl.queue_duplicate_lines(),
+        InputEvent::MoveLines(d) => ctrl.queue_move_lines(d),
+        InputEvent::DeleteLines => ctrl.queue_delete_lines(),
+        InputEvent::JoinLines => ctrl.queue_join_lines(),
+        InputEvent::BlockSelect => ctrl.queue_block_select(),
+        InputEvent::Filter => ctrl.start_prompt(InputEvent::Filter),
+        InputEvent::ShellInsert => ctrl.start_prompt(InputEvent::ShellInsert),
+        InputEvent::AddCursor(d) => ctrl.queue_add_cursor(d),
+        InputEvent::AddCursorNext => ctrl.queue_add_cursor_next(),
+        InputEvent::AddCursorMatches => ctrl.queue_add_cursor_matches(),
+        InputEvent::CommandLine => ctrl.start_prompt(InputEvent::CommandLine),
+    }
+    false
 }
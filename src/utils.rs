@@ -1,3 +1,5 @@
+use crate::config::Settings;
+use std::{env, path::PathBuf};
 use termsize::Size;
 
 // Returns current window size of a terminal, in rows and columns.
@@ -5,3 +7,55 @@ use termsize::Size;
 pub fn get_window_size() -> Size {
     termsize::get().unwrap()
 }
+
+// Encodes bytes as standard, padded base64. Used to pass copied text to the terminal in an OSC 52 sequence.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// Returns whether a program with the given name can be found somewhere on the PATH.
+pub fn program_exists(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()))
+}
+
+// Returns the path of one of gram's config files: $XDG_CONFIG_HOME/gram/<file>, or ~/.config/gram/<file>.
+pub fn config_path(file: &str) -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("gram").join(file))
+}
+
+// Returns the whitespace at the start of a line of text.
+pub fn leading_whitespace(text: &str) -> &str {
+    &text[..text.len() - text.trim_start().len()]
+}
+
+// Returns one level of indentation, matching the style of an existing indent: a tab if the indent is made of tabs,
+// and tab_width spaces if it's made of spaces. Without an existing indent to match, soft_tabs decides.
+pub fn indent_unit(indent: &str, settings: Settings) -> String {
+    if indent.starts_with('\t') || (indent.is_empty() && !settings.soft_tabs) {
+        "\t".to_string()
+    } else {
+        " ".repeat(settings.tab_width)
+    }
+}
@@ -0,0 +1,560 @@
+use crate::data::enums::{Direction, InputEvent};
+use termion::event::Key;
+
+// Functions for vim-style modal editing.
+// Nothing here touches the text directly. Keys typed in normal and visual mode are translated into the same InputEvents
+// that the default keys produce, so every command ends up running through the regular editor primitives.
+
+// Vim Mode. The mode that the modal layer is in.
+// Normal - Keys are commands: motions, operators and counts.
+// Insert - Keys go through the keymap as usual, until Esc goes back to normal mode.
+// Visual - Like normal mode, but motions extend a selection, and operators act on it.
+#[derive(Clone, Copy, PartialEq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+// Vim. Keeps track of the modal layer's state between keys.
+// pending holds the keys of a command that isn't finished yet (like "d" or "3g").
+// last_change holds the events of the last command that changed the text, for '.' to repeat. If that command went into
+// insert mode, everything typed before going back to normal mode is part of it -- insert_record collects those events.
+// visual_motions holds the motions made since visual mode started, so that a change made to the selection can be repeated
+// by selecting as much text again.
+pub struct Vim {
+    mode: VimMode,
+    pending: String,
+    last_change: Vec<InputEvent>,
+    insert_record: Option<Vec<InputEvent>>,
+    visual_motions: Vec<InputEvent>,
+}
+
+// Vim Command. A finished command, ready to run.
+// events are the InputEvents it runs, change is whether it changes the text (so '.' can repeat it), and mode is the
+// mode to switch to afterwards.
+struct VimCommand {
+    events: Vec<InputEvent>,
+    change: bool,
+    mode: VimMode,
+}
+
+// Parsed. The result of parsing the keys typed so far.
+// Incomplete - The keys are the start of a command. Wait for more.
+// Invalid - The keys can't be the start of any command. Throw them away.
+// Done - The keys make up a full command.
+enum Parsed {
+    Incomplete,
+    Invalid,
+    Done(VimCommand),
+}
+
+impl Vim {
+    // The modal layer starts out in normal mode.
+    pub fn new() -> Self {
+        Self {
+            mode: VimMode::Normal,
+            pending: String::new(),
+            last_change: Vec::new(),
+            insert_record: None,
+            visual_motions: Vec::new(),
+        }
+    }
+
+    // Returns a label for the current mode (and any unfinished command), for the status bar.
+    pub fn mode_label(&self) -> String {
+        let mode = match self.mode {
+            VimMode::Normal => "NORMAL",
+            VimMode::Insert => "INSERT",
+            VimMode::Visual => "VISUAL",
+        };
+        if self.pending.is_empty() {
+            mode.to_string()
+        } else {
+            format!("{} {}", mode, self.pending)
+        }
+    }
+
+    // Handles a key. Returns the InputEvents it translates to, or None if the key isn't handled by the modal layer and
+    // should go through the keymap instead.
+    // In insert mode, only Esc is handled here. In normal and visual mode, every character is a command (or part of one),
+    // and other keys -- arrows, Ctrl chords and the like -- go through the keymap.
+    pub fn handle_key(&mut self, k: Key) -> Option<Vec<InputEvent>> {
+        let c = match (self.mode, k) {
+            (VimMode::Insert, Key::Esc) => {
+                self.mode = VimMode::Normal;
+                if let Some(record) = self.insert_record.take() {
+                    self.last_change = record;
+                }
+                return Some(Vec::new());
+            }
+            (VimMode::Insert, _) => return None,
+            (VimMode::Visual, Key::Esc) => {
+                self.mode = VimMode::Normal;
+                self.pending.clear();
+                return Some(vec![InputEvent::Cancel]);
+            }
+            (VimMode::Normal, Key::Esc) => {
+                self.pending.clear();
+                return Some(vec![InputEvent::Cancel]);
+            }
+            (_, Key::Backspace) => 'h',
+            (VimMode::Normal, Key::Ctrl('r')) => {
+                self.pending.clear();
+                return Some(vec![InputEvent::Redo]);
+            }
+            (_, Key::Char(c)) => c,
+            _ => {
+                self.pending.clear();
+                return None;
+            }
+        };
+
+        self.pending.push(c);
+        let command = match self.parse() {
+            Parsed::Incomplete => return Some(Vec::new()),
+            Parsed::Invalid => {
+                self.pending.clear();
+                return Some(Vec::new());
+            }
+            Parsed::Done(command) => command,
+        };
+        self.pending.clear();
+
+        if command.change {
+            let mut change = Vec::new();
+            if self.mode == VimMode::Visual {
+                change.push(InputEvent::SetMark);
+                change.append(&mut self.visual_motions);
+            }
+            change.extend(command.events.iter().cloned());
+            if command.mode == VimMode::Insert {
+                self.insert_record = Some(change);
+            } else {
+                self.last_change = change;
+            }
+        }
+        match (self.mode, command.mode) {
+            (VimMode::Visual, VimMode::Visual) => {
+                self.visual_motions.extend(command.events.iter().cloned())
+            }
+            (_, VimMode::Visual) => self.visual_motions.clear(),
+            _ => (),
+        }
+        self.mode = command.mode;
+        Some(command.events)
+    }
+
+    // Records an event typed in insert mode, as part of the change that '.' repeats.
+    pub fn record(&mut self, evt: &InputEvent) {
+        if let Some(record) = &mut self.insert_record {
+            record.push(evt.clone());
+        }
+    }
+
+    // Parses the pending keys as a command: an optional count, then a command, an operator and a motion, or a motion.
+    fn parse(&self) -> Parsed {
+        let (count, rest) = split_count(&self.pending);
+        let mut chars = rest.chars();
+        let c = match chars.next() {
+            Some(c) => c,
+            None => return Parsed::Incomplete,
+        };
+        let after = chars.as_str();
+        let visual = self.mode == VimMode::Visual;
+
+        // operators
+        if let Some(op) = operator(c) {
+            if visual {
+                return Parsed::Done(apply_visual(op));
+            }
+            if after.is_empty() {
+                return Parsed::Incomplete;
+            }
+            if after.starts_with(c) && after.len() == 1 {
+                return Parsed::Done(apply_to_lines(op, count));
+            }
+            let (motion_count, motion) = split_count(after);
+            return match parse_motion(motion, count.saturating_mul(motion_count).min(COUNT_LIMIT)) {
+                Parsed::Done(motion) => Parsed::Done(apply_to_motion(op, motion)),
+                other => other,
+            };
+        }
+
+        // motions
+        match parse_motion(rest, count) {
+            Parsed::Done(mut motion) => {
+                motion.mode = self.mode;
+                motion.change = false;
+                return Parsed::Done(motion);
+            }
+            Parsed::Incomplete => return Parsed::Incomplete,
+            Parsed::Invalid => (),
+        }
+        if !after.is_empty() {
+            return Parsed::Invalid;
+        }
+
+        // everything else
+        let (events, change, mode) = match (c, visual) {
+            ('v', false) => (vec![InputEvent::SetMark], false, VimMode::Visual),
+            ('v', true) => (vec![InputEvent::Cancel], false, VimMode::Normal),
+            ('>', true) => (
+                vec![InputEvent::Write('\t'), InputEvent::Cancel],
+                false,
+                VimMode::Normal,
+            ),
+            ('<', true) => (
+                vec![InputEvent::Dedent, InputEvent::Cancel],
+                false,
+                VimMode::Normal,
+            ),
+            (_, true) => return Parsed::Invalid,
+            ('x', _) => (
+                repeat(&[InputEvent::DeleteChar(Direction::Right)], count),
+                true,
+                VimMode::Normal,
+            ),
+            ('X', _) => (
+                repeat(&[InputEvent::DeleteChar(Direction::Left)], count),
+                true,
+                VimMode::Normal,
+            ),
+            ('p', _) => (
+                repeat(&[InputEvent::YankAfter], count),
+                true,
+                VimMode::Normal,
+            ),
+            ('P', _) => (repeat(&[InputEvent::Yank], count), true, VimMode::Normal),
+            ('i', _) => (Vec::new(), true, VimMode::Insert),
+            ('a', _) => (
+                vec![InputEvent::Move(Direction::Right)],
+                true,
+                VimMode::Insert,
+            ),
+            ('A', _) => (
+                vec![InputEvent::Page(Direction::Right)],
+                true,
+                VimMode::Insert,
+            ),
+            ('I', _) => (
+                vec![InputEvent::Page(Direction::Left)],
+                true,
+                VimMode::Insert,
+            ),
+            ('o', _) => (
+                vec![InputEvent::Page(Direction::Right), InputEvent::Write('\n')],
+                true,
+                VimMode::Insert,
+            ),
+            ('O', _) => (
+                vec![
+                    InputEvent::Page(Direction::Left),
+                    InputEvent::Write('\n'),
+                    InputEvent::Move(Direction::Up),
+                ],
+                true,
+                VimMode::Insert,
+            ),
+            ('.', _) => (repeat(&self.last_change, count), false, VimMode::Normal),
+            ('n', _) => (
+                repeat(&[InputEvent::FindNext], count),
+                false,
+                VimMode::Normal,
+            ),
+            ('N', _) => (
+                repeat(&[InputEvent::FindPrev], count),
+                false,
+                VimMode::Normal,
+            ),
+            ('/', _) => (vec![InputEvent::Find], false, VimMode::Normal),
+            (':', _) => (vec![InputEvent::CommandLine], false, VimMode::Normal),
+            ('u', _) => (repeat(&[InputEvent::Undo], count), false, VimMode::Normal),
+            ('q', _) => (vec![InputEvent::MacroRecord], false, VimMode::Normal),
+            ('@', _) => (vec![InputEvent::MacroPlay], false, VimMode::Normal),
+            _ => return Parsed::Invalid,
+        };
+        Parsed::Done(VimCommand {
+            events,
+            change,
+            mode,
+        })
+    }
+}
+
+// Operator. The event that an operator runs on the text it covers, and whether it goes into insert mode afterwards.
+fn operator(c: char) -> Option<(InputEvent, bool)> {
+    match c {
+        'd' => Some((InputEvent::Cut, false)),
+        'c' => Some((InputEvent::Cut, true)),
+        'y' => Some((InputEvent::CopyBack, false)),
+        _ => None,
+    }
+}
+
+// Splits a count off of the front of some keys. A count can't start with 0, since 0 is a motion of its own.
+// Without a count, the count is 1. Counts are capped at COUNT_LIMIT (even ones too long to parse).
+fn split_count(keys: &str) -> (usize, &str) {
+    let digits = if keys.starts_with('0') {
+        0
+    } else {
+        keys.chars().take_while(|c| c.is_ascii_digit()).count()
+    };
+    let count = match digits {
+        0 => 1,
+        _ => keys[..digits]
+            .parse::<usize>()
+            .map_or(COUNT_LIMIT, |n| n.min(COUNT_LIMIT)),
+    };
+    (count, &keys[digits..])
+}
+
+// Repeats a list of events a number of times. Stops short of EVENT_LIMIT events, so that repeating a long change
+// (like 9999. after 9999x) can't run the editor out of memory.
+fn repeat(events: &[InputEvent], count: usize) -> Vec<InputEvent> {
+    let count = count.min(EVENT_LIMIT / events.len().max(1));
+    let mut out = Vec::new();
+    for _ in 0..count {
+        out.extend(events.iter().cloned());
+    }
+    out
+}
+
+// Parses a motion. A motion command's change field is used to mark motions that act on whole lines (j, k, gg and G),
+// which operators treat differently.
+fn parse_motion(keys: &str, count: usize) -> Parsed {
+    let (events, linewise) = match keys {
+        "h" => (repeat(&[InputEvent::Move(Direction::Left)], count), false),
+        "l" => (repeat(&[InputEvent::Move(Direction::Right)], count), false),
+        "j" => (repeat(&[InputEvent::Move(Direction::Down)], count), true),
+        "k" => (repeat(&[InputEvent::Move(Direction::Up)], count), true),
+        "w" => (repeat(&[InputEvent::NextWord], count), false),
+        "b" => (
+            repeat(&[InputEvent::MoveWord(Direction::Left)], count),
+            false,
+        ),
+        "e" => (
+            repeat(&[InputEvent::MoveWord(Direction::Right)], count),
+            false,
+        ),
+        "0" => (vec![InputEvent::Page(Direction::Left)], false),
+        "$" => (vec![InputEvent::Page(Direction::Right)], false),
+        "gg" => (
+            vec![
+                InputEvent::Page(Direction::Up),
+                InputEvent::Page(Direction::Left),
+            ],
+            true,
+        ),
+        // PgDn goes to the empty line past the end, so G steps back up onto the last line
+        "G" => (
+            vec![
+                InputEvent::Page(Direction::Down),
+                InputEvent::Move(Direction::Up),
+            ],
+            true,
+        ),
+        // a count after an operator, with its motion still to come
+        "" | "g" => return Parsed::Incomplete,
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Done(VimCommand {
+        events,
+        change: linewise,
+        mode: VimMode::Normal,
+    })
+}
+
+// Applies an operator to the text between the cursor and where a motion takes it, by selecting that text and then
+// cutting or copying it. Motions over whole lines select every line from the cursor's line to the motion's line.
+fn apply_to_motion(op: (InputEvent, bool), motion: VimCommand) -> VimCommand {
+    let (op_event, insert) = op;
+    let down = matches!(
+        motion.events.first(),
+        Some(InputEvent::Move(Direction::Down) | InputEvent::Page(Direction::Down))
+    );
+    let mut events = Vec::new();
+    if !motion.change {
+        events.push(InputEvent::SetMark);
+        events.extend(motion.events);
+    } else if down {
+        // from the start of this line, to the start of the line after the motion
+        events.push(InputEvent::Page(Direction::Left));
+        events.push(InputEvent::SetMark);
+        events.extend(motion.events);
+        events.push(InputEvent::Move(Direction::Down));
+        events.push(InputEvent::Page(Direction::Left));
+    } else {
+        // from the start of the line after this one, back to the start of the line the motion lands on
+        events.push(InputEvent::Move(Direction::Down));
+        events.push(InputEvent::Page(Direction::Left));
+        events.push(InputEvent::SetMark);
+        events.push(InputEvent::Move(Direction::Up));
+        events.extend(motion.events);
+        events.push(InputEvent::Page(Direction::Left));
+    }
+    let copy = matches!(op_event, InputEvent::CopyBack);
+    events.push(op_event);
+    VimCommand {
+        events,
+        change: !copy,
+        mode: if insert {
+            VimMode::Insert
+        } else {
+            VimMode::Normal
+        },
+    }
+}
+
+// Applies an operator to whole lines (dd, cc and yy), starting at the cursor's line.
+// The lines are selected first, line breaks and all, so that they go into the kill ring as a single entry. A change keeps
+// the line, but empties it.
+fn apply_to_lines(op: (InputEvent, bool), count: usize) -> VimCommand {
+    let (op_event, insert) = op;
+    let copy = matches!(op_event, InputEvent::CopyBack);
+    let mut events = vec![InputEvent::Page(Direction::Left), InputEvent::SetMark];
+    if insert {
+        events.extend(repeat(&[InputEvent::Move(Direction::Down)], count - 1));
+        events.push(InputEvent::Page(Direction::Right));
+    } else {
+        events.extend(repeat(&[InputEvent::Move(Direction::Down)], count));
+        events.push(InputEvent::Page(Direction::Left));
+    }
+    events.push(op_event);
+    VimCommand {
+        events,
+        change: !copy,
+        mode: if insert {
+            VimMode::Insert
+        } else {
+            VimMode::Normal
+        },
+    }
+}
+
+// Applies an operator to the selection in visual mode, and goes back to normal (or insert) mode.
+// Deleting or changing the selection can be repeated with '.', over as much text as it covered.
+fn apply_visual(op: (InputEvent, bool)) -> VimCommand {
+    let (op_event, insert) = op;
+    let copy = matches!(op_event, InputEvent::CopyBack);
+    VimCommand {
+        events: vec![op_event],
+        change: !copy,
+        mode: if insert {
+            VimMode::Insert
+        } else {
+            VimMode::Normal
+        },
+    }
+}
+
+// CONSTS //
+
+// The largest count a command can be given.
+const COUNT_LIMIT: usize = 9999;
+// The most events a repeated command can turn into.
+const EVENT_LIMIT: usize = 100_000;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use InputEvent::*;
+
+    // Types some keys into normal mode, and returns the events of the last one.
+    fn keys(vim: &mut Vim, keys: &str) -> Option<Vec<InputEvent>> {
+        keys.chars().map(|c| vim.handle_key(Key::Char(c))).last()?
+    }
+
+    #[test]
+    fn counts_and_motions() {
+        let mut vim = Vim::new();
+        assert_eq!(keys(&mut vim, "3j"), Some(vec![Move(Direction::Down); 3]));
+        assert_eq!(keys(&mut vim, "0"), Some(vec![Page(Direction::Left)]));
+        assert_eq!(
+            keys(&mut vim, "G"),
+            Some(vec![Page(Direction::Down), Move(Direction::Up)])
+        );
+        assert_eq!(keys(&mut vim, "99999l").map(|e| e.len()), Some(COUNT_LIMIT));
+        assert_eq!(keys(&mut vim, "g"), Some(Vec::new()));
+        assert_eq!(vim.mode_label(), "NORMAL g");
+        assert_eq!(keys(&mut vim, "g").map(|e| e.len()), Some(2));
+        assert_eq!(keys(&mut vim, "Z"), Some(Vec::new()));
+        assert_eq!(vim.mode_label(), "NORMAL");
+    }
+
+    #[test]
+    fn operators() {
+        let mut vim = Vim::new();
+        assert_eq!(
+            keys(&mut vim, "d2w"),
+            Some(vec![SetMark, NextWord, NextWord, Cut])
+        );
+        assert_eq!(
+            keys(&mut vim, "2dd"),
+            Some(vec![
+                Page(Direction::Left),
+                SetMark,
+                Move(Direction::Down),
+                Move(Direction::Down),
+                Page(Direction::Left),
+                Cut,
+            ])
+        );
+        assert_eq!(
+            keys(&mut vim, "cc"),
+            Some(vec![
+                Page(Direction::Left),
+                SetMark,
+                Page(Direction::Right),
+                Cut
+            ])
+        );
+        assert_eq!(vim.mode_label(), "INSERT");
+        assert_eq!(vim.handle_key(Key::Esc), Some(Vec::new()));
+        assert_eq!(
+            keys(&mut vim, "yy").and_then(|e| e.last().cloned()),
+            Some(CopyBack)
+        );
+    }
+
+    #[test]
+    fn repeat_last_change() {
+        let mut vim = Vim::new();
+        keys(&mut vim, "2x");
+        keys(&mut vim, "j");
+        assert_eq!(
+            keys(&mut vim, "2."),
+            Some(vec![DeleteChar(Direction::Right); 4])
+        );
+
+        // a change that goes into insert mode takes in everything typed until Esc
+        keys(&mut vim, "A");
+        assert_eq!(vim.handle_key(Key::Char('!')), None);
+        vim.record(&Write('!'));
+        vim.handle_key(Key::Esc);
+        assert_eq!(
+            keys(&mut vim, "."),
+            Some(vec![Page(Direction::Right), Write('!')])
+        );
+    }
+
+    #[test]
+    fn visual_changes_repeat_over_the_selection() {
+        let mut vim = Vim::new();
+        assert_eq!(keys(&mut vim, "v"), Some(vec![SetMark]));
+        keys(&mut vim, "2l");
+        assert_eq!(keys(&mut vim, "d"), Some(vec![Cut]));
+        assert_eq!(vim.mode_label(), "NORMAL");
+        assert_eq!(
+            keys(&mut vim, "."),
+            Some(vec![
+                SetMark,
+                Move(Direction::Right),
+                Move(Direction::Right),
+                Cut
+            ])
+        );
+        keys(&mut vim, "vly");
+        assert_eq!(keys(&mut vim, ".").map(|e| e.len()), Some(4));
+    }
+}